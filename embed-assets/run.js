@@ -1,6 +1,10 @@
 
 const assert = require('assert');
+const util = require('util');
 
+// elm-torture parses lines starting with this marker from stderr to report
+// structured failures.
+const FAILURE_MARKER = 'elm-torture-failure: ';
 
 module.exports = function (generated, output) {
     const { ports = [], flags, logs : expectedLogs = '' } = output;
@@ -16,6 +20,14 @@ module.exports = function (generated, output) {
         Date.now = () => 0;
     }
     generated._randSeed = () => 0;
+    let failed = false;
+
+    function reportFailure(failure) {
+        failed = true;
+        console.error(FAILURE_MARKER + JSON.stringify(failure));
+        process.exitCode = 1;
+    }
+
     const app = generated.Elm.Main.init(flags !== undefined ? { flags } : undefined);
     let portEventIndex = 0;
 
@@ -55,11 +67,16 @@ module.exports = function (generated, output) {
                         expectedName === portName,
                         `Port event ${portEventIndex} should be command ${expectedName} but command ${portName} received (with value ${data}).`,
                     );
-                    assert.deepStrictEqual(
-                        data,
-                        expectedData,
-                        `Wrong data sent to port ${portName} during port event ${portEventIndex + 1}`,
-                    );
+                    if (!util.isDeepStrictEqual(data, expectedData)) {
+                        reportFailure({
+                            kind: 'port-mismatch',
+                            port: portName,
+                            eventIndex: portEventIndex + 1,
+                            expected: expectedData,
+                            actual: data,
+                        });
+                        process.exit();
+                    }
                     portEventIndex += 1;
                     sendIfNextEventSubscription();
                 })
@@ -70,14 +87,20 @@ module.exports = function (generated, output) {
     sendIfNextEventSubscription();
 
     process.on('exit', () => {
+        if (failed) {
+            return;
+        }
         assert.strictEqual(
             portEventIndex, ports.length,
             `There have been ${portEventIndex} port events but should have been exactly ${ports.length} port events.`,
         );
-        assert.strictEqual(
-            actualLogs, expectedLogs,
-            `Unexpected logs`,
-        );
+        if (actualLogs !== expectedLogs) {
+            reportFailure({
+                kind: 'logs-mismatch',
+                expected: expectedLogs,
+                actual: actualLogs,
+            });
+        }
     });
 
 }
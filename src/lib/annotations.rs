@@ -0,0 +1,90 @@
+use super::formatting;
+use super::suite::{CompileAndRunResults, Outcome, SscceRunResult};
+use std::fmt::{self, Write as _};
+use std::path::Path;
+
+/// See <https://docs.github.com/en/actions/reference/workflow-commands-for-github-actions>.
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+/// The file of the suite that an annotation for `outcome` should point at.
+/// Compile failures are most likely caused by the elm code, everything else
+/// by an expectation in `output.json`.
+fn annotated_file(outcome: Outcome) -> Option<&'static str> {
+    match outcome {
+        Outcome::CompileFailure => Some("Main.elm"),
+        Outcome::RunFailure | Outcome::ExpectedCompileFailure | Outcome::ExpectedRunFailure => {
+            Some("output.json")
+        }
+        Outcome::SuiteError
+        | Outcome::InfrastructureError
+        | Outcome::Success
+        | Outcome::AllowedCompileFailure
+        | Outcome::AllowedRunFailure => None,
+    }
+}
+
+/// Render one GitHub Actions `::error` workflow command per unexpected
+/// failure.
+pub fn github<Ps: AsRef<Path>>(results: &[CompileAndRunResults<Ps>]) -> impl fmt::Display + '_ {
+    formatting::easy_format(move |f| {
+        formatting::without_colors(|| {
+            for CompileAndRunResults { suite, errors, .. } in results {
+                let mut run_types = errors.iter().collect::<Vec<_>>();
+                run_types.sort_by_key(|((compiler, opt_level), _)| {
+                    (compiler.to_string(), opt_level.id())
+                });
+                for (
+                    (compiler, opt_level),
+                    SscceRunResult {
+                        out_dir,
+                        retries,
+                        error,
+                        ..
+                    },
+                ) in run_types
+                {
+                    let outcome = Outcome::of(error.as_ref());
+                    let error = match error {
+                        Some(error) if outcome.is_failure() => error,
+                        _ => continue,
+                    };
+                    let mut properties = String::new();
+                    if let Some(file) = annotated_file(outcome) {
+                        let _ = write!(
+                            properties,
+                            "file={},",
+                            escape_property(&suite.as_ref().join(file).display().to_string())
+                        );
+                    }
+                    let _ = write!(
+                        properties,
+                        "title={}",
+                        escape_property(&format!(
+                            "{} ({outcome} with {compiler} in {opt_level} optimisation mode)",
+                            suite.as_ref().display()
+                        ))
+                    );
+                    writeln!(
+                        f,
+                        "::error {}::{}",
+                        properties,
+                        escape_data(
+                            formatting::compile_and_run_error(error, suite, out_dir, *retries)
+                                .to_string()
+                                .trim_end()
+                        )
+                    )?;
+                }
+            }
+            Ok(())
+        })
+    })
+}
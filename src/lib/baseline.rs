@@ -0,0 +1,265 @@
+use super::config::OptimizationLevel;
+use super::find_suites;
+use super::suite::{CompileAndRunResults, Outcome};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Identifies one suite compiled by one compiler at one optimisation level.
+/// Suites are identified by their path relative to the suites directory so
+/// that baselines can be compared between checkouts.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key {
+    pub suite: PathBuf,
+    pub compiler: String,
+    pub opt_level: OptimizationLevel,
+}
+
+impl Key {
+    pub fn new(
+        suite: &Path,
+        suites_dir: &Path,
+        compiler: &str,
+        opt_level: OptimizationLevel,
+    ) -> Self {
+        Self {
+            suite: find_suites::relative_path(suite, suites_dir).to_path_buf(),
+            compiler: compiler.to_string(),
+            opt_level,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Entry {
+    suite: PathBuf,
+    compiler: String,
+    opt_level: OptimizationLevel,
+    outcome: Outcome,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct BaselineFile {
+    schema_version: u32,
+    outcomes: Vec<Entry>,
+}
+
+#[derive(Debug, Default)]
+pub struct Baseline(BTreeMap<Key, Outcome>);
+
+#[derive(Debug)]
+pub struct Change {
+    pub key: Key,
+    /// None if the combination was not part of the baseline.
+    pub before: Option<Outcome>,
+    pub after: Outcome,
+}
+
+#[derive(Debug, Default)]
+pub struct Comparison {
+    pub newly_failing: Vec<Change>,
+    pub newly_passing: Vec<Change>,
+    pub still_failing: Vec<Change>,
+}
+
+impl Baseline {
+    pub fn from_results<Ps: AsRef<Path>>(
+        results: &[CompileAndRunResults<Ps>],
+        suites_dir: &Path,
+    ) -> Self {
+        let mut outcomes = BTreeMap::new();
+        for CompileAndRunResults { suite, errors, .. } in results {
+            for ((compiler, opt_level), result) in errors {
+                outcomes.insert(
+                    Key::new(suite.as_ref(), suites_dir, compiler.name(), *opt_level),
+                    Outcome::of(result.error.as_ref()),
+                );
+            }
+        }
+        Self(outcomes)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format!("Baseline file {} not found: {}", path.display(), e))?;
+        let BaselineFile {
+            schema_version,
+            outcomes,
+        } = serde_json::from_reader(file).map_err(|e| {
+            format!(
+                "Could not parse file {} as a baseline: {}",
+                path.display(),
+                e
+            )
+        })?;
+        if schema_version != SCHEMA_VERSION {
+            return Err(format!(
+                "Baseline file {} has version {} but elm-torture only understands version {}",
+                path.display(),
+                schema_version,
+                SCHEMA_VERSION
+            ));
+        }
+        Ok(Self(
+            outcomes
+                .into_iter()
+                .map(|e| {
+                    (
+                        Key {
+                            suite: e.suite,
+                            compiler: e.compiler,
+                            opt_level: e.opt_level,
+                        },
+                        e.outcome,
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let baseline = BaselineFile {
+            schema_version: SCHEMA_VERSION,
+            outcomes: self
+                .0
+                .iter()
+                .map(|(key, outcome)| Entry {
+                    suite: key.suite.clone(),
+                    compiler: key.compiler.clone(),
+                    opt_level: key.opt_level,
+                    outcome: *outcome,
+                })
+                .collect(),
+        };
+        let mut file = File::create(path)?;
+        serde_json::to_writer_pretty(&mut file, &baseline)?;
+        writeln!(&mut file)
+    }
+
+    /// Compare the outcomes of this run against those of an `older` run.
+    /// Combinations that are missing from this run are ignored.
+    pub fn compare(&self, older: &Self) -> Comparison {
+        let mut comparison = Comparison::default();
+        for (key, after) in &self.0 {
+            let before = older.0.get(key).copied();
+            let was_failing = before.is_some_and(Outcome::is_failure);
+            let change = Change {
+                key: key.clone(),
+                before,
+                after: *after,
+            };
+            match (was_failing, after.is_failure()) {
+                (false, true) => comparison.newly_failing.push(change),
+                (true, false) => comparison.newly_passing.push(change),
+                (true, true) => comparison.still_failing.push(change),
+                (false, false) => {}
+            }
+        }
+        comparison
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Baseline, Change, Key};
+    use crate::lib::config::OptimizationLevel;
+    use crate::lib::suite::Outcome;
+
+    fn key(suite: &str) -> Key {
+        Key {
+            suite: suite.into(),
+            compiler: "elm".to_string(),
+            opt_level: OptimizationLevel::Dev,
+        }
+    }
+
+    fn baseline(outcomes: &[(&str, Outcome)]) -> Baseline {
+        Baseline(
+            outcomes
+                .iter()
+                .map(|(suite, outcome)| (key(suite), *outcome))
+                .collect(),
+        )
+    }
+
+    fn suites(changes: &[Change]) -> Vec<&str> {
+        changes
+            .iter()
+            .map(|change| change.key.suite.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn compare_sorts_changes() {
+        let older = baseline(&[
+            ("broke", Outcome::Success),
+            ("fixed", Outcome::RunFailure),
+            ("still-broken", Outcome::CompileFailure),
+            ("still-fine", Outcome::Success),
+            ("now-allowed", Outcome::RunFailure),
+            ("removed", Outcome::RunFailure),
+        ]);
+        let newer = baseline(&[
+            ("broke", Outcome::ExpectedRunFailure),
+            ("fixed", Outcome::Success),
+            ("still-broken", Outcome::InfrastructureError),
+            ("still-fine", Outcome::AllowedCompileFailure),
+            ("now-allowed", Outcome::AllowedRunFailure),
+            ("added-failing", Outcome::SuiteError),
+            ("added-passing", Outcome::Success),
+        ]);
+        let comparison = newer.compare(&older);
+        assert_eq!(
+            suites(&comparison.newly_failing),
+            ["added-failing", "broke"]
+        );
+        assert_eq!(suites(&comparison.newly_passing), ["fixed", "now-allowed"]);
+        assert_eq!(suites(&comparison.still_failing), ["still-broken"]);
+
+        let added = &comparison.newly_failing[0];
+        assert_eq!(added.before, None);
+        assert_eq!(added.after, Outcome::SuiteError);
+        let still = &comparison.still_failing[0];
+        assert_eq!(still.before, Some(Outcome::CompileFailure));
+        assert_eq!(still.after, Outcome::InfrastructureError);
+    }
+
+    #[test]
+    fn keys_include_compiler_and_opt_level() {
+        let older = baseline(&[("suite", Outcome::Success)]);
+        let mut newer = baseline(&[("suite", Outcome::Success)]);
+        newer.0.insert(
+            Key {
+                opt_level: OptimizationLevel::Optimize,
+                ..key("suite")
+            },
+            Outcome::RunFailure,
+        );
+        newer.0.insert(
+            Key {
+                compiler: "elm-patched".to_string(),
+                ..key("suite")
+            },
+            Outcome::RunFailure,
+        );
+        let comparison = newer.compare(&older);
+        assert_eq!(comparison.newly_failing.len(), 2);
+        assert!(comparison.newly_passing.is_empty());
+    }
+
+    #[test]
+    fn round_trips_through_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("baseline.json");
+        let written = baseline(&[("a/1", Outcome::Success), ("b/2", Outcome::RunFailure)]);
+        written.write(&path).unwrap();
+        assert_eq!(Baseline::read(&path).unwrap().0, written.0);
+    }
+}
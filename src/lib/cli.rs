@@ -1,9 +1,17 @@
+use crate::lib::baseline::Baseline;
 use crate::lib::config;
+use crate::lib::config_layers::{self, Layer, Layers, Source};
+use crate::lib::find_suites;
+use crate::lib::json_report::SuiteDurations;
+use crate::lib::shard::Shard;
+use crate::lib::tags::TagExpr;
 use clap::Clap;
+use std::str::FromStr;
 use std::{ffi::OsStr, path::PathBuf};
 use std::{fs::File, path::Path};
 
 #[derive(Clap)]
+#[allow(clippy::struct_excessive_bools)]
 #[clap(
     version = "0.0.2",
     author = "Harry Sarson <harry.sarson@hotmail.co.uk>",
@@ -11,7 +19,14 @@ use std::{fs::File, path::Path};
 )]
 struct Opts {
     #[clap(short, long = "config", about = "Set config file", parse(try_from_os_str = read_config_file))]
-    config_from_file: Option<config::Config>,
+    config_from_file: Option<Layer>,
+
+    #[clap(
+        long,
+        value_name = "NAME",
+        about = "Use the settings of the profile <NAME> defined in a config file."
+    )]
+    profile: Option<String>,
 
     #[clap(flatten)]
     config: config::Config,
@@ -23,25 +38,171 @@ struct Opts {
     )]
     suites: PathBuf,
 
-    #[clap(long, value_name = "FILE", about = "Dump the configuration to FILE.")]
+    #[clap(
+        long,
+        value_name = "PATTERN",
+        number_of_values = 1,
+        about = "Only run suites whose path (relative to the suites directory) matches the glob <PATTERN>. Prefix <PATTERN> with `re:` to use a regex instead. Can be given more than once."
+    )]
+    filter: Vec<find_suites::Pattern>,
+
+    #[clap(
+        long,
+        value_name = "PATTERN",
+        number_of_values = 1,
+        about = "Skip suites whose path (relative to the suites directory) matches <PATTERN>. Takes the same patterns as --filter."
+    )]
+    exclude: Vec<find_suites::Pattern>,
+
+    #[clap(
+        long,
+        value_name = "EXPRESSION",
+        about = "Only run suites whose tags match <EXPRESSION>, for example `json & !http` or `records | tail-calls`."
+    )]
+    tags: Option<TagExpr>,
+
+    #[clap(
+        long,
+        value_name = "EXPRESSION",
+        about = "Skip suites whose tags match <EXPRESSION>."
+    )]
+    skip_tags: Option<TagExpr>,
+
+    #[clap(
+        long,
+        value_name = "I/N",
+        about = "Split the suites into <N> disjoint shards and only run the <I>th (counting from 1)."
+    )]
+    shard: Option<Shard>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        requires = "shard",
+        about = "Balance shards using the suite durations recorded in FILE (written by --report-json).",
+        parse(try_from_os_str = read_suite_durations_file)
+    )]
+    shard_timings: Option<SuiteDurations>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        about = "Dump the configuration (combining config files, ELM_TORTURE_* environment variables and command line flags) to FILE."
+    )]
     show_config: Option<PathBuf>,
 
+    #[clap(
+        long,
+        about = "Show the configuration of the suites directory (and any subdirectories with their own elm-torture.json) and where each value comes from."
+    )]
+    explain_config: bool,
+
     #[clap(long, about = "Stop running on the first failed suite.")]
     fail_fast: bool,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        about = "Write a JUnit XML report of the results to FILE."
+    )]
+    junit: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        about = "Write a machine readable JSON report of the results to FILE."
+    )]
+    report_json: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        default_value = "10",
+        about = "List the <N> slowest suites after running (0 to disable)."
+    )]
+    slowest: usize,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        about = "Save the outcome of every suite to FILE for use with --compare-baseline."
+    )]
+    save_baseline: Option<PathBuf>,
+
+    #[clap(
+        long,
+        value_name = "FILE",
+        about = "Report changes since the run saved in FILE. Only regressions cause a non-zero exit code.",
+        parse(try_from_os_str = read_baseline_file)
+    )]
+    compare_baseline: Option<Baseline>,
+
+    #[clap(
+        long,
+        value_name = "STYLE",
+        default_value = "list",
+        possible_values = &["list", "matrix"],
+        about = "How to summarise the results: a list per compiler and optimisation level or a matrix of suites against them."
+    )]
+    summary: SummaryStyle,
+
+    #[clap(long, about = "Do not report progress whilst suites are running.")]
+    no_progress: bool,
+
+    #[clap(
+        long,
+        about = "Also print a GitHub Actions error annotation for every unexpected failure."
+    )]
+    github_annotations: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryStyle {
+    List,
+    Matrix,
+}
+
+impl FromStr for SummaryStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "list" => Ok(Self::List),
+            "matrix" => Ok(Self::Matrix),
+            _ => Err(format!("Invalid summary style: {s}")),
+        }
+    }
 }
 
 pub enum Task {
     DumpConfig(PathBuf),
+    ExplainConfig,
     RunSuites(PathBuf),
 }
 
 pub struct Instructions {
+    /// The configuration that applies to the whole suites directory.
     pub config: config::Config,
+    pub config_layers: Layers,
+    pub filter: Vec<find_suites::Pattern>,
+    pub exclude: Vec<find_suites::Pattern>,
+    pub tags: Option<TagExpr>,
+    pub skip_tags: Option<TagExpr>,
+    pub shard: Option<Shard>,
+    pub shard_timings: Option<SuiteDurations>,
     pub fail_fast: bool,
+    pub junit: Option<PathBuf>,
+    pub report_json: Option<PathBuf>,
+    pub slowest: usize,
+    pub save_baseline: Option<PathBuf>,
+    pub compare_baseline: Option<Baseline>,
+    pub summary: SummaryStyle,
+    pub progress: bool,
+    pub github_annotations: bool,
     pub task: Task,
 }
 
-fn read_config_file(config_path: &OsStr) -> Result<config::Config, String> {
+fn read_config_file(config_path: &OsStr) -> Result<Layer, String> {
     let file = File::open(config_path).map_err(|e| {
         format!(
             "Config file {} not found: {}",
@@ -49,34 +210,99 @@ fn read_config_file(config_path: &OsStr) -> Result<config::Config, String> {
             e
         )
     })?;
-    serde_json::from_reader(file).map_err(|e| {
+    let config = serde_json::from_reader(file).map_err(|e| {
         format!(
             "Could not parse file {} as json config: {}",
             AsRef::<Path>::as_ref(config_path).to_string_lossy(),
             e
         )
+    })?;
+    Ok(Layer {
+        source: Source::File(config_path.into()),
+        config,
     })
 }
 
+fn read_baseline_file(baseline_path: &OsStr) -> Result<Baseline, String> {
+    Baseline::read(baseline_path)
+}
+
+fn read_suite_durations_file(report_path: &OsStr) -> Result<SuiteDurations, String> {
+    SuiteDurations::read(report_path)
+}
+
 pub fn get_cli_task() -> Instructions {
     let Opts {
         suites,
+        filter,
+        exclude,
+        tags,
+        skip_tags,
+        shard,
+        shard_timings,
         config_from_file,
+        profile,
         fail_fast,
         show_config,
+        explain_config,
+        junit,
+        report_json,
+        slowest,
+        save_baseline,
+        compare_baseline,
+        summary,
+        no_progress,
+        github_annotations,
         config: config_from_cli,
         ..
     } = Opts::parse();
 
-    let config = if let Some(c) = config_from_file {
-        c.overwrite_with(config_from_cli)
+    let config_layers = config_layers::read_env()
+        .and_then(|config_from_env| {
+            Layers::discover(
+                &suites,
+                config_from_file.into_iter().collect(),
+                vec![
+                    Layer {
+                        source: Source::Environment,
+                        config: config_from_env,
+                    },
+                    Layer {
+                        source: Source::CommandLine,
+                        config: config_from_cli,
+                    },
+                ],
+                profile.as_deref(),
+            )
+        })
+        .unwrap_or_else(|e| {
+            clap::Error::with_description(format!("{e}\n"), clap::ErrorKind::ValueValidation).exit()
+        });
+
+    let task = if explain_config {
+        Task::ExplainConfig
     } else {
-        config_from_cli
+        show_config.map_or_else(|| Task::RunSuites(suites), Task::DumpConfig)
     };
 
     Instructions {
-        config,
+        config: config_layers.root(),
+        config_layers,
+        filter,
+        exclude,
+        tags,
+        skip_tags,
+        shard,
+        shard_timings,
         fail_fast,
-        task: show_config.map_or_else(|| Task::RunSuites(suites), Task::DumpConfig),
+        junit,
+        report_json,
+        slowest,
+        save_baseline,
+        compare_baseline,
+        summary,
+        progress: !no_progress,
+        github_annotations,
+        task,
     }
 }
@@ -0,0 +1,80 @@
+use super::config::OptimizationLevel;
+use super::fingerprint::{CompilerHashes, Fingerprint, Hasher};
+use super::suite::ElmCompilerPath;
+use log::debug;
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Whether the JS for a suite was found in the compile cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Lookup {
+    Hit,
+    Miss,
+}
+
+/// Generated JS from previous successful compilations, keyed by a hash of
+/// everything that goes into the compilation: the suite's elm.json,
+/// targets.txt and source directories, the compiler binary and the
+/// optimisation level.
+pub struct CompileCache {
+    dir: PathBuf,
+    compiler_hashes: Arc<CompilerHashes>,
+}
+
+impl CompileCache {
+    pub fn new(dir: PathBuf, compiler_hashes: Arc<CompilerHashes>) -> Self {
+        Self {
+            dir,
+            compiler_hashes,
+        }
+    }
+
+    pub fn key(
+        &self,
+        suite: &Path,
+        compiler: &ElmCompilerPath,
+        opt_level: OptimizationLevel,
+    ) -> io::Result<Fingerprint> {
+        let mut hasher = Hasher::new();
+        hasher.compiler(&self.compiler_hashes, compiler)?;
+        hasher.field("opt-level", Some(opt_level.id().as_bytes()));
+
+        let elm_json = fs::read(suite.join("elm.json"))?;
+        hasher.field("elm.json", Some(&elm_json));
+        hasher.optional_file("targets.txt", &suite.join("targets.txt"))?;
+        hasher.source_dirs(suite, &elm_json)?;
+
+        Ok(hasher.finish())
+    }
+
+    fn path(&self, key: &Fingerprint) -> PathBuf {
+        self.dir.join(format!("{key}.js"))
+    }
+
+    /// Copy the cached JS for `key` to `out_file`, if there is any.
+    pub fn restore(&self, key: &Fingerprint, out_file: &Path) -> Lookup {
+        match fs::copy(self.path(key), out_file) {
+            Ok(_) => Lookup::Hit,
+            Err(e) => {
+                if e.kind() != io::ErrorKind::NotFound {
+                    debug!("Could not read from the compile cache: {e}");
+                }
+                Lookup::Miss
+            }
+        }
+    }
+
+    /// Add `out_file` to the cache. The file is moved into place once fully
+    /// written so that other elm-torture processes never see part of it.
+    pub fn store(&self, key: &Fingerprint, out_file: &Path) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut tmp = tempfile::NamedTempFile::new_in(&self.dir)?;
+        io::copy(&mut fs::File::open(out_file)?, &mut tmp)?;
+        tmp.persist(self.path(key)).map_err(|e| e.error)?;
+        Ok(())
+    }
+}
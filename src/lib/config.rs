@@ -1,11 +1,21 @@
+use super::suite::StdlibVariant;
 use clap::Clap;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::convert::{Infallible, TryFrom};
+use std::env;
+use std::mem;
+use std::num::NonZeroUsize;
+use std::path::Path;
 use std::str::FromStr;
 use std::string::String;
+use std::thread;
 use std::time::Duration;
 use std::{fmt, path::PathBuf};
 
-#[derive(Debug, Deserialize, Serialize, Clap, PartialEq, Eq, Clone, Copy, Hash)]
+#[derive(
+    Debug, Deserialize, Serialize, Clap, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash,
+)]
 #[serde(rename_all = "kebab-case")]
 pub enum OptimizationLevel {
     Debug,
@@ -45,13 +55,143 @@ impl fmt::Display for OptimizationLevel {
     }
 }
 
+/// The kinds of failure that elm-torture reports using distinct exit codes
+/// (see exit-codes.md).
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Clone, Copy, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum FailureCategory {
+    CompileFailure,
+    RunFailure,
+    UnexpectedSuccess,
+    Timeout,
+    Infrastructure,
+    Other,
+}
+
+impl FailureCategory {
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::CompileFailure => "compile-failure",
+            Self::RunFailure => "run-failure",
+            Self::UnexpectedSuccess => "unexpected-success",
+            Self::Timeout => "timeout",
+            Self::Infrastructure => "infrastructure",
+            Self::Other => "other",
+        }
+    }
+}
+
+/// An elm compiler to test: either the name of (or path to) its binary or a
+/// definition giving more details.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged, try_from = "UncheckedElmCompiler")]
+pub enum ElmCompiler {
+    Binary(String),
+    Defined(CompilerDefinition),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum UncheckedElmCompiler {
+    Binary(String),
+    Defined(CompilerDefinition),
+}
+
+impl TryFrom<UncheckedElmCompiler> for ElmCompiler {
+    type Error = String;
+
+    fn try_from(compiler: UncheckedElmCompiler) -> Result<Self, Self::Error> {
+        match compiler {
+            UncheckedElmCompiler::Binary(path) => Ok(Self::Binary(path)),
+            UncheckedElmCompiler::Defined(definition) => {
+                if let Some(name) = &definition.name {
+                    if name.is_empty()
+                        || name.chars().all(|c| c == '.')
+                        || name.contains(['/', '\\'])
+                    {
+                        return Err(format!(
+                            "invalid compiler name `{name}`: a name must not be empty, made only of dots or contain path separators"
+                        ));
+                    }
+                }
+                Ok(Self::Defined(definition))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+#[serde(rename_all = "kebab-case")]
+pub struct CompilerDefinition {
+    /// Used for the compiler in reports, defaults to `path`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub path: String,
+    /// Added to every `make` command, after the optimisation level flags.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Seeds the `ELM_HOME`s used by this compiler instead of the user's.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub elm_home: Option<PathBuf>,
+    /// Skips running `<path> --stdlib-variant` to find out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stdlib_variant: Option<StdlibVariant>,
+}
+
+impl ElmCompiler {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Binary(path) => path,
+            Self::Defined(definition) => definition.name.as_ref().unwrap_or(&definition.path),
+        }
+    }
+
+    pub fn definition(&self) -> CompilerDefinition {
+        match self {
+            Self::Binary(path) => CompilerDefinition {
+                name: None,
+                path: path.clone(),
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                elm_home: None,
+                stdlib_variant: None,
+            },
+            Self::Defined(definition) => definition.clone(),
+        }
+    }
+}
+
+impl FromStr for ElmCompiler {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::Binary(s.to_string()))
+    }
+}
+
+/// The settings that can be set for part of the suites directory.
+const PER_SUITE: [&str; 5] = [
+    "opt-levels",
+    "compiler-max-retries",
+    "run-timeout",
+    "compile-timeout",
+    "scratch-copy",
+];
+
+/// The settings that are turned on or off, by `--<flag>` or `--<flag>=false`
+/// on the command line.
+pub const FLAGS: [&str; 3] = ["scratch-copy", "no-cache", "incremental"];
+
 // fn serialize_os_str<S>(str: &OsStr, s: S) -> Result<S::Ok, S::Error>
 // where
 //     S: serde::Serializer,
 // {
 // }
 
-#[derive(Debug, Default, Deserialize, Serialize, Clap)]
+#[derive(Debug, Default, Clone, Deserialize, Serialize, Clap)]
 #[serde(deny_unknown_fields)]
 #[serde(rename_all = "kebab-case")]
 pub struct Config {
@@ -59,10 +199,10 @@ pub struct Config {
         long,
         multiple(false),
         use_delimiter(true),
-        about = "Path to elm compiler."
+        about = "Elm compilers to test, as names of (or paths to) their binaries. Define compilers with extra arguments, environment variables or readable names in a config file."
     )]
     #[serde(skip_serializing_if = "Option::is_none")]
-    elm_compilers: Option<Vec<String>>,
+    elm_compilers: Option<Vec<ElmCompiler>>,
     #[clap(long, about = "Path to node.")]
     #[serde(skip_serializing_if = "Option::is_none")]
     node: Option<String>,
@@ -88,8 +228,77 @@ pub struct Config {
         about = "Report run time failure if SSCCE takes more than <DURATION> to run.",
         parse(try_from_str = humantime::parse_duration)
     )]
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default, with = "duration", skip_serializing_if = "Option::is_none")]
     run_timeout: Option<Duration>,
+    #[clap(
+        long,
+        value_name = "DURATION",
+        about = "Report compile time failure if compiling an SSCCE takes more than <DURATION> (by default there is no limit).",
+        parse(try_from_str = humantime::parse_duration)
+    )]
+    #[serde(default, with = "duration", skip_serializing_if = "Option::is_none")]
+    compile_timeout: Option<Duration>,
+    #[clap(
+        long,
+        value_name = "CATEGORY",
+        multiple(false),
+        use_delimiter(true),
+        possible_values = &[
+            "compile-failure",
+            "run-failure",
+            "unexpected-success",
+            "timeout",
+            "infrastructure",
+            "other",
+        ],
+        about = "Report failures of these categories without them causing a non-zero exit code."
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    non_fatal: Option<Vec<FailureCategory>>,
+    #[clap(
+        long,
+        value_name = "BOOL",
+        min_values(0),
+        require_equals(true),
+        default_missing_value("true"),
+        about = "Compile a copy of each suite in the out dir so that the suites directory is never modified."
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scratch_copy: Option<bool>,
+    #[clap(
+        long,
+        value_name = "N",
+        about = "Run at most <N> compilations at once (defaults to the number of CPUs)."
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compile_jobs: Option<usize>,
+    #[clap(
+        long,
+        value_name = "DIRECTORY",
+        about = "Where to cache compiled SSCCEs and passes between runs (defaults to elm-torture in the user cache directory)."
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache_dir: Option<PathBuf>,
+    #[clap(
+        long,
+        value_name = "BOOL",
+        min_values(0),
+        require_equals(true),
+        default_missing_value("true"),
+        about = "Always compile SSCCEs rather than using previously compiled JS. Use this if the compiler is a wrapper script: the cache only notices changes to the script itself."
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    no_cache: Option<bool>,
+    #[clap(
+        long,
+        value_name = "BOOL",
+        min_values(0),
+        require_equals(true),
+        default_missing_value("true"),
+        about = "Skip SSCCEs that passed in a previous run with the same suite files, compiler, settings, node and elm-torture."
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    incremental: Option<bool>,
 
     #[clap(
         long,
@@ -98,6 +307,12 @@ pub struct Config {
     )]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub out_dir: Option<PathBuf>,
+
+    /// Named sets of settings that override the rest of the config file when
+    /// selected with `--profile`.
+    #[clap(skip)]
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, Config>,
 }
 
 impl Config {
@@ -118,13 +333,150 @@ impl Config {
             opt_levels: merge!(opt_levels),
             compiler_max_retries: merge!(compiler_max_retries),
             run_timeout: merge!(run_timeout),
+            compile_timeout: merge!(compile_timeout),
+            non_fatal: merge!(non_fatal),
+            scratch_copy: merge!(scratch_copy),
+            compile_jobs: merge!(compile_jobs),
+            cache_dir: merge!(cache_dir),
+            no_cache: merge!(no_cache),
+            incremental: merge!(incremental),
             out_dir: merge!(out_dir),
+            profiles: {
+                let mut profiles = self.profiles;
+                profiles.extend(other.profiles);
+                profiles
+            },
         }
     }
 
-    pub fn elm_compilers(&self) -> &Vec<String> {
+    pub fn profile_names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.keys().map(String::as_str)
+    }
+
+    /// Remove every profile from the config, returning the one called `name`.
+    pub fn take_profile(&mut self, name: &str) -> Option<Config> {
+        mem::take(&mut self.profiles).remove(name)
+    }
+
+    /// The name (as used in config files) and value of every setting, None if
+    /// the setting is not set.
+    pub fn settings(&self) -> Vec<(&'static str, Option<String>)> {
+        vec![
+            (
+                "elm-compilers",
+                self.elm_compilers.as_ref().map(|compilers| {
+                    compilers
+                        .iter()
+                        .map(ElmCompiler::name)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                }),
+            ),
+            ("node", self.node.clone()),
+            (
+                "opt-levels",
+                self.opt_levels.as_ref().map(|levels| {
+                    levels
+                        .iter()
+                        .map(|level| level.id())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                }),
+            ),
+            (
+                "compiler-max-retries",
+                self.compiler_max_retries.map(|n| n.to_string()),
+            ),
+            (
+                "run-timeout",
+                self.run_timeout
+                    .map(|d| humantime::format_duration(d).to_string()),
+            ),
+            (
+                "compile-timeout",
+                self.compile_timeout
+                    .map(|d| humantime::format_duration(d).to_string()),
+            ),
+            (
+                "non-fatal",
+                self.non_fatal.as_ref().map(|categories| {
+                    categories
+                        .iter()
+                        .map(|category| category.id())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                }),
+            ),
+            ("scratch-copy", self.scratch_copy.map(|b| b.to_string())),
+            ("compile-jobs", self.compile_jobs.map(|n| n.to_string())),
+            (
+                "cache-dir",
+                self.cache_dir.as_ref().map(|d| d.display().to_string()),
+            ),
+            ("no-cache", self.no_cache.map(|b| b.to_string())),
+            ("incremental", self.incremental.map(|b| b.to_string())),
+            (
+                "out-dir",
+                self.out_dir.as_ref().map(|d| d.display().to_string()),
+            ),
+        ]
+    }
+
+    /// The values used for settings that are not set.
+    pub fn default_settings() -> Vec<(&'static str, Option<String>)> {
+        let defaults = Self::default();
+        Self {
+            elm_compilers: Some(defaults.elm_compilers().to_vec()),
+            node: Some(defaults.node().to_string()),
+            opt_levels: Some(defaults.opt_levels().to_vec()),
+            compiler_max_retries: Some(defaults.compiler_max_retries()),
+            run_timeout: Some(defaults.run_timeout()),
+            scratch_copy: Some(defaults.scratch_copy()),
+            compile_jobs: Some(defaults.compile_jobs()),
+            cache_dir: defaults.cache_dir(),
+            no_cache: Some(defaults.no_cache()),
+            incremental: Some(defaults.incremental()),
+            ..defaults
+        }
+        .settings()
+        .into_iter()
+        .map(|(name, value)| {
+            let value = value.or_else(|| match name {
+                "compile-timeout" => Some("none".to_string()),
+                "out-dir" => Some("(a temporary directory)".to_string()),
+                _ => None,
+            });
+            (name, value)
+        })
+        .collect()
+    }
+
+    /// The settings that can differ between suites.
+    pub fn per_suite_settings(&self) -> Vec<(&'static str, Option<String>)> {
+        self.settings()
+            .into_iter()
+            .filter(|(name, _)| PER_SUITE.contains(name))
+            .collect()
+    }
+
+    /// The names of the settings that are set but must be the same for every
+    /// suite (and so cannot be set for part of the suites directory).
+    pub fn run_wide_settings(&self) -> Vec<&'static str> {
+        let mut run_wide = self
+            .settings()
+            .into_iter()
+            .filter(|(name, value)| value.is_some() && !PER_SUITE.contains(name))
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        if !self.profiles.is_empty() {
+            run_wide.push("profiles");
+        }
+        run_wide
+    }
+
+    pub fn elm_compilers(&self) -> &[ElmCompiler] {
         lazy_static::lazy_static! {
-            static ref ELM: Vec<String> = vec!["elm".to_string()];
+            static ref ELM: Vec<ElmCompiler> = vec![ElmCompiler::Binary("elm".to_string())];
         }
         &self.elm_compilers.as_ref().unwrap_or(&*ELM)
     }
@@ -148,6 +500,93 @@ impl Config {
     pub fn run_timeout(&self) -> Duration {
         self.run_timeout.unwrap_or_else(|| Duration::new(10, 0))
     }
+
+    pub fn compile_timeout(&self) -> Option<Duration> {
+        self.compile_timeout
+    }
+
+    pub fn scratch_copy(&self) -> bool {
+        self.scratch_copy.unwrap_or(false)
+    }
+
+    pub fn compile_jobs(&self) -> usize {
+        self.compile_jobs
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get))
+            .max(1)
+    }
+
+    /// None if there is nowhere to put the cache.
+    pub fn cache_dir(&self) -> Option<PathBuf> {
+        self.cache_dir.clone().or_else(|| {
+            let user_cache_dir = if cfg!(windows) {
+                env::var_os("LOCALAPPDATA").map(PathBuf::from)
+            } else if let Some(xdg_cache_home) = env::var_os("XDG_CACHE_HOME") {
+                Some(PathBuf::from(xdg_cache_home))
+            } else {
+                env::var_os("HOME").map(|home| Path::new(&home).join(".cache"))
+            };
+            user_cache_dir.map(|dir| dir.join("elm-torture"))
+        })
+    }
+
+    pub fn no_cache(&self) -> bool {
+        self.no_cache.unwrap_or(false)
+    }
+
+    pub fn incremental(&self) -> bool {
+        self.incremental.unwrap_or(false)
+    }
+
+    pub fn is_fatal(&self, category: FailureCategory) -> bool {
+        !self
+            .non_fatal
+            .as_ref()
+            .is_some_and(|non_fatal| non_fatal.contains(&category))
+    }
+}
+
+/// Durations in config files are written as in output.json, for example
+/// `"30s"`. The `{"secs": 30, "nanos": 0}` form written by older versions of
+/// `--show-config` is still accepted.
+mod duration {
+    use serde::de::{self, value::MapAccessDeserializer};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt;
+    use std::time::Duration;
+
+    struct Visitor;
+
+    impl<'de> de::Visitor<'de> for Visitor {
+        type Value = Option<Duration>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a duration such as \"30s\"")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            humantime::parse_duration(v)
+                .map(Some)
+                .map_err(|e| E::custom(format!("invalid duration {v:?}: {e}")))
+        }
+
+        fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+            Duration::deserialize(MapAccessDeserializer::new(map)).map(Some)
+        }
+
+        fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+            Ok(None)
+        }
+    }
+
+    // serde passes a reference to the field.
+    #[allow(clippy::ref_option)]
+    pub fn serialize<S: Serializer>(d: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+        humantime_serde::serialize(d, s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Duration>, D::Error> {
+        d.deserialize_any(Visitor)
+    }
 }
 
 #[derive(Debug)]
@@ -171,3 +610,107 @@ impl fmt::Display for InvalidOptimizationLevel {
         write!(f, "Invalid optimization level: {}", self.0)
     }
 }
+
+#[derive(Debug)]
+pub struct InvalidFailureCategory(String);
+
+impl FromStr for FailureCategory {
+    type Err = InvalidFailureCategory;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "compile-failure" => Self::CompileFailure,
+            "run-failure" => Self::RunFailure,
+            "unexpected-success" => Self::UnexpectedSuccess,
+            "timeout" => Self::Timeout,
+            "infrastructure" => Self::Infrastructure,
+            "other" => Self::Other,
+            _ => return Err(InvalidFailureCategory(s.to_string())),
+        })
+    }
+}
+
+impl fmt::Display for InvalidFailureCategory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid failure category: {}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, ElmCompiler};
+    use std::time::Duration;
+
+    fn parse(json: &str) -> Result<Config, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn durations_are_human_readable() {
+        let config = parse(r#"{"run-timeout": "1m 30s", "compile-timeout": "3s"}"#).unwrap();
+        assert_eq!(config.run_timeout(), Duration::from_secs(90));
+        assert_eq!(config.compile_timeout(), Some(Duration::from_secs(3)));
+        assert_eq!(
+            serde_json::to_value(config.serialize()).unwrap(),
+            serde_json::json!({"run-timeout": "1m 30s", "compile-timeout": "3s"})
+        );
+    }
+
+    #[test]
+    fn durations_accept_the_old_struct_form() {
+        let config = parse(r#"{"run-timeout": {"secs": 2, "nanos": 500000000}}"#).unwrap();
+        assert_eq!(config.run_timeout(), Duration::from_millis(2500));
+        assert_eq!(
+            parse(r#"{"compile-timeout": null}"#)
+                .unwrap()
+                .compile_timeout(),
+            None
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for invalid in &[r#""3""#, r#""soon""#, "3", "[]"] {
+            assert!(
+                parse(&format!(r#"{{"run-timeout": {invalid}}}"#)).is_err(),
+                "{:?} should not parse",
+                invalid
+            );
+        }
+    }
+
+    fn compiler_named(name: &str) -> Result<Config, serde_json::Error> {
+        parse(&format!(
+            r#"{{"elm-compilers": [{{"name": {}, "path": "./elm"}}]}}"#,
+            serde_json::Value::from(name)
+        ))
+    }
+
+    #[test]
+    fn accepts_compiler_definitions() {
+        let config = parse(r#"{"elm-compilers": ["elm", {"path": "./elm"}]}"#).unwrap();
+        let names = config
+            .elm_compilers()
+            .iter()
+            .map(ElmCompiler::name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["elm", "./elm"]);
+        for valid in &["elm-patched", "elm 0.19.1", ".elm", "a..b"] {
+            assert_eq!(
+                compiler_named(valid).unwrap().elm_compilers()[0].name(),
+                *valid
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_unsafe_compiler_names() {
+        for invalid in &["", ".", "..", "...", "a/b", "../elm", "a\\b"] {
+            assert!(
+                compiler_named(invalid).is_err(),
+                "{:?} should not be a valid compiler name",
+                invalid
+            );
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use super::config::{self, Config};
+use clap::Clap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The name of the config files that are discovered in the suites directory.
+pub const FILE_NAME: &str = "elm-torture.json";
+
+/// The prefix of the environment variables that configure elm-torture.
+pub const ENV_PREFIX: &str = "ELM_TORTURE_";
+
+/// Where a configuration value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Profile { name: String, path: PathBuf },
+    Environment,
+    CommandLine,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Profile { name, path } => write!(f, "profile {} in {}", name, path.display()),
+            Self::Environment => write!(f, "environment"),
+            Self::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Layer {
+    pub source: Source,
+    pub config: Config,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Reading {
+        path: PathBuf,
+        error: io::Error,
+    },
+    Parsing {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    RunWideSetting {
+        path: PathBuf,
+        setting: &'static str,
+    },
+    Environment {
+        variable: String,
+        message: String,
+    },
+    NestedProfiles {
+        path: PathBuf,
+        profile: String,
+    },
+    UnknownProfile {
+        profile: String,
+        available: Vec<String>,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Reading { path, error } => {
+                write!(f, "Could not read config file {}: {}", path.display(), error)
+            }
+            Self::Parsing { path, error } => write!(
+                f,
+                "Could not parse file {} as json config: {}",
+                path.display(),
+                error
+            ),
+            Self::RunWideSetting { path, setting } => write!(
+                f,
+                "{} sets `{}` which applies to every suite so can only be set in the {} at the root of the suites directory",
+                path.display(),
+                setting,
+                FILE_NAME
+            ),
+            Self::Environment { variable, message } => write!(
+                f,
+                "Invalid value for environment variable {variable}: {message}"
+            ),
+            Self::NestedProfiles { path, profile } => write!(
+                f,
+                "Profile {} in {} defines its own profiles, which is not allowed",
+                profile,
+                path.display()
+            ),
+            Self::UnknownProfile { profile, available } if available.is_empty() => write!(
+                f,
+                "Profile {profile} not found as no config file defines any profiles"
+            ),
+            Self::UnknownProfile { profile, available } => write!(
+                f,
+                "Profile {} not found. The config files define: {}",
+                profile,
+                available.join(", ")
+            ),
+        }
+    }
+}
+
+pub fn read_file(path: &Path) -> Result<Config, Error> {
+    let contents = fs::read(path).map_err(|error| Error::Reading {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    serde_json::from_slice(&contents).map_err(|error| Error::Parsing {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// The environment variable that sets `setting`, for example
+/// `ELM_TORTURE_RUN_TIMEOUT` for `run-timeout`.
+pub fn env_var(setting: &str) -> String {
+    format!("{}{}", ENV_PREFIX, setting.to_uppercase().replace('-', "_"))
+}
+
+/// Read the settings given by `ELM_TORTURE_*` environment variables. Each
+/// takes the same values as the command line flag of the same name. Flags
+/// are turned on by `true` or `1` and off by `false` or `0`. Variables set to
+/// the empty string are ignored.
+pub fn read_env() -> Result<Config, Error> {
+    let mut config = Config::default();
+    for (setting, _) in Config::default().settings() {
+        let variable = env_var(setting);
+        let value = match env::var_os(&variable) {
+            Some(value) if !value.is_empty() => value,
+            _ => continue,
+        };
+        let value = if config::FLAGS.contains(&setting) {
+            match value.to_str() {
+                Some("true" | "1") => OsString::from("true"),
+                Some("false" | "0") => OsString::from("false"),
+                _ => {
+                    return Err(Error::Environment {
+                        variable,
+                        message: "expected `true` or `false`".to_string(),
+                    })
+                }
+            }
+        } else {
+            value
+        };
+        let mut arg = OsString::from(format!("--{setting}="));
+        arg.push(value);
+        let from_env =
+            Config::try_parse_from(vec![OsString::from("elm-torture"), arg]).map_err(|e| {
+                Error::Environment {
+                    message: e
+                        .to_string()
+                        .lines()
+                        .next()
+                        .unwrap_or_default()
+                        .trim_start_matches("error: ")
+                        .to_string(),
+                    variable,
+                }
+            })?;
+        config = config.overwrite_with(from_env);
+    }
+    Ok(config)
+}
+
+/// Replace `layer` by itself without its profiles, followed by the profile
+/// called `name` if it defines one.
+fn select_profile(mut layer: Layer, name: &str) -> Result<Vec<Layer>, Error> {
+    let profile = layer.config.take_profile(name);
+    let mut selected = vec![];
+    if let (Source::File(path), Some(profile)) = (&layer.source, profile) {
+        if profile.profile_names().next().is_some() {
+            return Err(Error::NestedProfiles {
+                path: path.clone(),
+                profile: name.to_string(),
+            });
+        }
+        selected.push(Layer {
+            source: Source::Profile {
+                name: name.to_string(),
+                path: path.clone(),
+            },
+            config: profile,
+        });
+    }
+    selected.insert(0, layer);
+    Ok(selected)
+}
+
+fn discover(dir: &Path, files: &mut Vec<(PathBuf, Layer)>) -> Result<(), Error> {
+    let reading = |error| Error::Reading {
+        path: dir.to_path_buf(),
+        error,
+    };
+    let file = dir.join(FILE_NAME);
+    if file.is_file() {
+        files.push((
+            dir.to_path_buf(),
+            Layer {
+                config: read_file(&file)?,
+                source: Source::File(file),
+            },
+        ));
+    }
+    let mut entries = fs::read_dir(dir)
+        .map_err(reading)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(reading)?;
+    entries.sort_by_key(fs::DirEntry::file_name);
+    for entry in entries {
+        if entry.file_name() != "elm-stuff" && entry.file_type().map_err(reading)?.is_dir() {
+            discover(&entry.path(), files)?;
+        }
+    }
+    Ok(())
+}
+
+/// The configuration of a run of elm-torture, made of layers that each
+/// override the ones before: the file given by `--config`, the
+/// elm-torture.json files in the suites directory (shallower files first, each
+/// applying to the suites beneath it), the `ELM_TORTURE_*` environment
+/// variables and the command line flags. The profile selected with
+/// `--profile` comes straight after the config file that defines it.
+#[derive(Debug)]
+pub struct Layers {
+    suites_dir: PathBuf,
+    below: Vec<Layer>,
+    /// Sorted so that deeper directories come later.
+    files: Vec<(PathBuf, Layer)>,
+    above: Vec<Layer>,
+}
+
+impl Layers {
+    pub fn discover(
+        suites_dir: &Path,
+        below: Vec<Layer>,
+        above: Vec<Layer>,
+        profile: Option<&str>,
+    ) -> Result<Self, Error> {
+        let mut files = Vec::new();
+        if suites_dir.is_dir() {
+            discover(suites_dir, &mut files)?;
+        }
+        files.sort_by_key(|(dir, _)| dir.components().count());
+        for (dir, layer) in &files {
+            if dir == suites_dir {
+                continue;
+            }
+            if let (Source::File(path), Some(setting)) =
+                (&layer.source, layer.config.run_wide_settings().first())
+            {
+                return Err(Error::RunWideSetting {
+                    path: path.clone(),
+                    setting,
+                });
+            }
+        }
+        let mut layers = Self {
+            suites_dir: suites_dir.to_path_buf(),
+            below,
+            files,
+            above,
+        };
+        if let Some(profile) = profile {
+            layers.select_profile(profile)?;
+        }
+        Ok(layers)
+    }
+
+    fn select_profile(&mut self, name: &str) -> Result<(), Error> {
+        let root = self.root();
+        if root.profile_names().all(|available| available != name) {
+            return Err(Error::UnknownProfile {
+                profile: name.to_string(),
+                available: root.profile_names().map(String::from).collect(),
+            });
+        }
+        let mut below = vec![];
+        for layer in self.below.drain(..) {
+            below.extend(select_profile(layer, name)?);
+        }
+        self.below = below;
+        let mut files = vec![];
+        for (dir, layer) in self.files.drain(..) {
+            files.extend(
+                select_profile(layer, name)?
+                    .into_iter()
+                    .map(|layer| (dir.clone(), layer)),
+            );
+        }
+        self.files = files;
+        Ok(())
+    }
+
+    /// The layers that apply to the suites in `dir`, lowest precedence first.
+    fn applicable<'a>(&'a self, dir: &'a Path) -> impl Iterator<Item = &'a Layer> + 'a {
+        self.below
+            .iter()
+            .chain(
+                self.files
+                    .iter()
+                    .filter(move |(file_dir, _)| dir.starts_with(file_dir))
+                    .map(|(_, layer)| layer),
+            )
+            .chain(&self.above)
+    }
+
+    pub fn config_for(&self, suite: &Path) -> Config {
+        self.applicable(suite)
+            .fold(Config::default(), |config, layer| {
+                config.overwrite_with(layer.config.clone())
+            })
+    }
+
+    /// The configuration that applies to the whole suites directory.
+    pub fn root(&self) -> Config {
+        self.config_for(&self.suites_dir)
+    }
+
+    pub fn suites_dir(&self) -> &Path {
+        &self.suites_dir
+    }
+
+    /// Directories beneath the suites directory that have their own config
+    /// file.
+    pub fn nested_dirs(&self) -> impl Iterator<Item = &Path> {
+        self.files
+            .iter()
+            .map(|(dir, _)| dir.as_path())
+            .filter(move |dir| *dir != self.suites_dir)
+    }
+
+    /// Every setting with its effective value (for the suites in `dir`) and
+    /// where that value came from.
+    pub fn explain(&self, dir: &Path) -> Vec<(&'static str, Option<String>, Source)> {
+        let mut explained = Config::default_settings()
+            .into_iter()
+            .map(|(name, value)| (name, value, Source::Default))
+            .collect::<Vec<_>>();
+        for layer in self.applicable(dir) {
+            for ((_, value, source), (_, layer_value)) in
+                explained.iter_mut().zip(layer.config.settings())
+            {
+                if layer_value.is_some() {
+                    *value = layer_value;
+                    *source = layer.source.clone();
+                }
+            }
+        }
+        explained
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{env_var, read_env, Error, Layer, Layers, Source, FILE_NAME};
+    use crate::lib::config::Config;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::time::Duration;
+
+    fn layer(source: Source, json: &str) -> Layer {
+        Layer {
+            source,
+            config: serde_json::from_str(json).unwrap(),
+        }
+    }
+
+    fn write_config(dir: &Path, json: &str) {
+        fs::create_dir_all(dir).unwrap();
+        fs::write(dir.join(FILE_NAME), json).unwrap();
+    }
+
+    fn retries(layers: &Layers, dir: &Path) -> usize {
+        layers.config_for(dir).compiler_max_retries()
+    }
+
+    #[test]
+    fn later_layers_take_precedence() {
+        let suites = tempfile::tempdir().unwrap();
+        let root = suites.path();
+        write_config(root, r#"{"compiler-max-retries": 2, "run-timeout": "2s"}"#);
+        write_config(&root.join("a"), r#"{"compiler-max-retries": 3}"#);
+        write_config(&root.join("a/b"), r#"{"compiler-max-retries": 4}"#);
+        let below = vec![layer(
+            Source::File("config.json".into()),
+            r#"{"compiler-max-retries": 1, "node": "my-node"}"#,
+        )];
+
+        let layers = Layers::discover(root, below, vec![], None).unwrap();
+        assert_eq!(retries(&layers, root), 2);
+        assert_eq!(retries(&layers, &root.join("a")), 3);
+        assert_eq!(retries(&layers, &root.join("a/b/suite")), 4);
+        assert_eq!(retries(&layers, &root.join("c/suite")), 2);
+        assert_eq!(layers.root().node(), "my-node");
+        assert_eq!(
+            layers.config_for(&root.join("a/b")).run_timeout(),
+            Duration::from_secs(2)
+        );
+
+        let above = vec![
+            layer(Source::Environment, r#"{"compiler-max-retries": 5}"#),
+            layer(Source::CommandLine, r#"{"run-timeout": "6s"}"#),
+        ];
+        let layers = Layers::discover(root, vec![], above, None).unwrap();
+        let config = layers.config_for(&root.join("a/b"));
+        assert_eq!(config.compiler_max_retries(), 5);
+        assert_eq!(config.run_timeout(), Duration::from_secs(6));
+        let explained = layers.explain(&root.join("a/b"));
+        let source = |setting| {
+            explained
+                .iter()
+                .find(|(name, _, _)| *name == setting)
+                .map(|(_, _, source)| source.clone())
+                .unwrap()
+        };
+        assert_eq!(source("compiler-max-retries"), Source::Environment);
+        assert_eq!(source("run-timeout"), Source::CommandLine);
+        assert_eq!(source("node"), Source::Default);
+    }
+
+    #[test]
+    fn later_layers_turn_flags_off() {
+        let suites = tempfile::tempdir().unwrap();
+        let root = suites.path();
+        write_config(root, r#"{"scratch-copy": true}"#);
+        write_config(&root.join("a"), r#"{"scratch-copy": false}"#);
+        let layers = Layers::discover(root, vec![], vec![], None).unwrap();
+        assert!(layers.root().scratch_copy());
+        assert!(!layers.config_for(&root.join("a")).scratch_copy());
+
+        let above = vec![
+            layer(Source::Environment, r#"{"scratch-copy": true}"#),
+            layer(Source::CommandLine, r#"{"scratch-copy": false}"#),
+        ];
+        let layers = Layers::discover(root, vec![], above, None).unwrap();
+        assert!(!layers.root().scratch_copy());
+    }
+
+    #[test]
+    fn profiles_override_only_their_file() {
+        let suites = tempfile::tempdir().unwrap();
+        let root = suites.path();
+        write_config(
+            root,
+            r#"{
+                "compiler-max-retries": 2,
+                "profiles": {"ci": {"compiler-max-retries": 7, "run-timeout": "7s"}}
+            }"#,
+        );
+        write_config(&root.join("a"), r#"{"compiler-max-retries": 3}"#);
+        let above = vec![layer(Source::CommandLine, r#"{"run-timeout": "9s"}"#)];
+
+        let layers = Layers::discover(root, vec![], above, Some("ci")).unwrap();
+        assert_eq!(retries(&layers, root), 7);
+        assert_eq!(retries(&layers, &root.join("a")), 3);
+        assert_eq!(layers.root().run_timeout(), Duration::from_secs(9));
+
+        let layers = Layers::discover(root, vec![], vec![], None).unwrap();
+        assert_eq!(retries(&layers, root), 2);
+
+        match Layers::discover(root, vec![], vec![], Some("nightly")) {
+            Err(Error::UnknownProfile { available, .. }) => assert_eq!(available, ["ci"]),
+            other => panic!("expected an unknown profile, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn nested_files_cannot_set_run_wide_settings() {
+        let suites = tempfile::tempdir().unwrap();
+        let root = suites.path();
+        write_config(&root.join("a"), r#"{"node": "my-node"}"#);
+        match Layers::discover(root, vec![], vec![], None) {
+            Err(Error::RunWideSetting { setting, .. }) => assert_eq!(setting, "node"),
+            other => panic!("expected a run wide setting error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reads_environment_variables() {
+        // Every case is in one test as the environment is shared by the
+        // whole test binary.
+        let set = |setting: &str, value: &str| env::set_var(env_var(setting), value);
+        let clear = || {
+            for (setting, _) in Config::default().settings() {
+                env::remove_var(env_var(setting));
+            }
+        };
+
+        clear();
+        set("run-timeout", "1m 30s");
+        set("opt-levels", "dev,optimize");
+        set("compiler-max-retries", "");
+        set("scratch-copy", "1");
+        set("incremental", "false");
+        let config = read_env().unwrap();
+        assert_eq!(config.run_timeout(), Duration::from_secs(90));
+        assert_eq!(config.opt_levels().len(), 2);
+        assert_eq!(
+            config.compiler_max_retries(),
+            Config::default().compiler_max_retries()
+        );
+        assert!(config.scratch_copy());
+        // An explicit false is kept so that it can override a config file.
+        let settings = config.settings();
+        assert!(settings.contains(&("incremental", Some("false".to_string()))));
+        assert!(settings.contains(&("compiler-max-retries", None)));
+
+        set("scratch-copy", "yes");
+        match read_env() {
+            Err(Error::Environment { variable, .. }) => {
+                assert_eq!(variable, "ELM_TORTURE_SCRATCH_COPY");
+            }
+            other => panic!("expected an environment error, got {:?}", other),
+        }
+        set("scratch-copy", "0");
+
+        set("run-timeout", "soon");
+        match read_env() {
+            Err(Error::Environment { variable, .. }) => {
+                assert_eq!(variable, "ELM_TORTURE_RUN_TIMEOUT");
+            }
+            other => panic!("expected an environment error, got {:?}", other),
+        }
+        clear();
+    }
+}
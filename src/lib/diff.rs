@@ -0,0 +1,254 @@
+use serde_json::Value;
+use std::fmt::Write as _;
+
+/// A location at which two json values differ. `None` indicates that the
+/// location does not exist in that value.
+#[derive(Debug)]
+pub struct JsonDifference<'a> {
+    pub path: String,
+    pub expected: Option<&'a Value>,
+    pub actual: Option<&'a Value>,
+}
+
+/// List the innermost paths (in a `$.field[index]` syntax) at which
+/// `expected` and `actual` differ.
+pub fn json_differences<'a>(expected: &'a Value, actual: &'a Value) -> Vec<JsonDifference<'a>> {
+    fn go<'a>(
+        path: &mut String,
+        expected: Option<&'a Value>,
+        actual: Option<&'a Value>,
+        differences: &mut Vec<JsonDifference<'a>>,
+    ) {
+        let len = path.len();
+        match (expected, actual) {
+            (Some(Value::Object(e)), Some(Value::Object(a))) => {
+                let mut keys = e.keys().chain(a.keys()).collect::<Vec<_>>();
+                keys.sort();
+                keys.dedup();
+                for key in keys {
+                    if key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        let _ = write!(path, ".{key}");
+                    } else {
+                        let _ = write!(path, "[{}]", Value::String(key.clone()));
+                    }
+                    go(path, e.get(key), a.get(key), differences);
+                    path.truncate(len);
+                }
+            }
+            (Some(Value::Array(e)), Some(Value::Array(a))) => {
+                for i in 0..e.len().max(a.len()) {
+                    let _ = write!(path, "[{i}]");
+                    go(path, e.get(i), a.get(i), differences);
+                    path.truncate(len);
+                }
+            }
+            (e, a) if e != a => differences.push(JsonDifference {
+                path: path.clone(),
+                expected: e,
+                actual: a,
+            }),
+            _ => {}
+        }
+    }
+
+    let mut differences = Vec::new();
+    go(
+        &mut String::from("$"),
+        Some(expected),
+        Some(actual),
+        &mut differences,
+    );
+    differences
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Line<'a> {
+    Both(&'a str),
+    Expected(&'a str),
+    Actual(&'a str),
+}
+
+/// A run of changed lines surrounded by (at most `context`) unchanged lines.
+#[derive(Debug)]
+pub struct Hunk<'a> {
+    /// One-based line number of the first line of this hunk in `expected`.
+    pub expected_start: usize,
+    pub expected_len: usize,
+    /// One-based line number of the first line of this hunk in `actual`.
+    pub actual_start: usize,
+    pub actual_len: usize,
+    pub lines: Vec<Line<'a>>,
+}
+
+/// Diff `expected` and `actual` line by line (using the longest common
+/// subsequence).
+pub fn line_diff<'a>(expected: &'a str, actual: &'a str) -> Vec<Line<'a>> {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the length of the longest common subsequence of
+    // expected[i..] and actual[j..].
+    let mut lcs = vec![vec![0_usize; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::with_capacity(expected.len().max(actual.len()));
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() && j < actual.len() {
+        if expected[i] == actual[j] {
+            lines.push(Line::Both(expected[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            lines.push(Line::Expected(expected[i]));
+            i += 1;
+        } else {
+            lines.push(Line::Actual(actual[j]));
+            j += 1;
+        }
+    }
+    lines.extend(expected[i..].iter().map(|l| Line::Expected(l)));
+    lines.extend(actual[j..].iter().map(|l| Line::Actual(l)));
+    lines
+}
+
+/// Group a line diff into the hunks of a unified diff.
+pub fn hunks<'a>(lines: &[Line<'a>], context: usize) -> Vec<Hunk<'a>> {
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Line::Both(_)))
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+
+    // Ranges of `lines` to show, merging those whose context overlaps.
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for i in changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(lines.len());
+        match ranges.last_mut() {
+            Some((_, last_end)) if start <= *last_end => *last_end = end,
+            _ => ranges.push((start, end)),
+        }
+    }
+
+    let position = |upto: usize| {
+        lines[..upto]
+            .iter()
+            .fold((0, 0), |(e, a), line| match line {
+                Line::Both(_) => (e + 1, a + 1),
+                Line::Expected(_) => (e + 1, a),
+                Line::Actual(_) => (e, a + 1),
+            })
+    };
+
+    ranges
+        .into_iter()
+        .map(|(start, end)| {
+            let (expected_before, actual_before) = position(start);
+            let (expected_after, actual_after) = position(end);
+            Hunk {
+                expected_start: expected_before + 1,
+                expected_len: expected_after - expected_before,
+                actual_start: actual_before + 1,
+                actual_len: actual_after - actual_before,
+                lines: lines[start..end].to_vec(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{hunks, json_differences, line_diff, Line};
+    use serde_json::{json, Value};
+
+    fn differences(
+        expected: &Value,
+        actual: &Value,
+    ) -> Vec<(String, Option<Value>, Option<Value>)> {
+        json_differences(expected, actual)
+            .into_iter()
+            .map(|d| (d.path, d.expected.cloned(), d.actual.cloned()))
+            .collect()
+    }
+
+    #[test]
+    fn equal_json_has_no_differences() {
+        let value = json!({"a": [1, {"b": null}], "c": "d"});
+        assert!(json_differences(&value, &value).is_empty());
+    }
+
+    #[test]
+    fn json_paths_point_at_innermost_difference() {
+        assert_eq!(
+            differences(
+                &json!({"a": {"b": [1, 2]}, "odd key": true, "gone": 1}),
+                &json!({"a": {"b": [1, 3, 4]}, "odd key": false, "new": 2}),
+            ),
+            vec![
+                ("$.a.b[1]".to_string(), Some(json!(2)), Some(json!(3))),
+                ("$.a.b[2]".to_string(), None, Some(json!(4))),
+                ("$.gone".to_string(), Some(json!(1)), None),
+                ("$.new".to_string(), None, Some(json!(2))),
+                (
+                    "$[\"odd key\"]".to_string(),
+                    Some(json!(true)),
+                    Some(json!(false))
+                ),
+            ]
+        );
+        assert_eq!(
+            differences(&json!([1]), &json!({"0": 1})),
+            vec![("$".to_string(), Some(json!([1])), Some(json!({"0": 1})))]
+        );
+    }
+
+    #[test]
+    fn line_diff_keeps_longest_common_subsequence() {
+        assert_eq!(
+            line_diff("a\nb\nc\nd", "a\nc\nx\nd\ne"),
+            vec![
+                Line::Both("a"),
+                Line::Expected("b"),
+                Line::Both("c"),
+                Line::Actual("x"),
+                Line::Both("d"),
+                Line::Actual("e"),
+            ]
+        );
+        assert_eq!(line_diff("", "a"), vec![Line::Actual("a")]);
+        assert_eq!(line_diff("a", ""), vec![Line::Expected("a")]);
+        assert!(line_diff("", "").is_empty());
+    }
+
+    #[test]
+    fn hunks_merge_overlapping_context() {
+        let expected = "1\n2\n3\n4\n5\n6\n7\n8\n9";
+        let lines = line_diff(expected, "1\nx\n3\n4\n5\n6\n7\n8");
+        let summary = |context| {
+            hunks(&lines, context)
+                .into_iter()
+                .map(|h| {
+                    (
+                        h.expected_start,
+                        h.expected_len,
+                        h.actual_start,
+                        h.actual_len,
+                        h.lines.len(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(summary(1), vec![(1, 3, 1, 3, 4), (8, 2, 8, 1, 2)]);
+        assert_eq!(summary(3), vec![(1, 9, 1, 8, 10)]);
+        assert!(hunks(&line_diff(expected, expected), 3).is_empty());
+    }
+}
@@ -2,6 +2,7 @@ use std::fs;
 use std::io;
 use std::path::Path;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 #[derive(Debug)]
 pub enum Error {
@@ -37,3 +38,68 @@ fn add_suites(suites_dir: &Path, suites_list: &mut Vec<PathBuf>) -> Result<(), E
     }
     Ok(())
 }
+
+/// The path of `suite` relative to the directory it was found in.
+pub fn relative_path<'a>(suite: &'a Path, suites_dir: &Path) -> &'a Path {
+    suite.strip_prefix(suites_dir).unwrap_or(suite)
+}
+
+/// A glob (or a regex if prefixed by `re:`) matched against the path of a
+/// suite relative to the suites directory.
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Glob(Box<globset::GlobMatcher>),
+    Regex(Box<regex::Regex>),
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(re) = s.strip_prefix("re:") {
+            regex::Regex::new(re)
+                .map(|re| Self::Regex(Box::new(re)))
+                .map_err(|e| format!("Invalid regex {re:?}: {e}"))
+        } else {
+            globset::GlobBuilder::new(s)
+                .literal_separator(false)
+                .build()
+                .map(|glob| Self::Glob(Box::new(glob.compile_matcher())))
+                .map_err(|e| format!("Invalid glob {s:?}: {e}"))
+        }
+    }
+}
+
+impl Pattern {
+    fn is_match(&self, relative_suite: &Path) -> bool {
+        match self {
+            Self::Glob(glob) => glob.is_match(relative_suite),
+            Self::Regex(re) => re.is_match(
+                &relative_suite
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/"),
+            ),
+        }
+    }
+}
+
+/// Keep the suites that match any of `filters` (or all suites if there are
+/// no filters) and none of `excludes`.
+pub fn filter_suites(
+    suites: &[PathBuf],
+    suites_dir: &Path,
+    filters: &[Pattern],
+    excludes: &[Pattern],
+) -> Box<[PathBuf]> {
+    suites
+        .iter()
+        .filter(|suite| {
+            let relative = relative_path(suite, suites_dir);
+            (filters.is_empty() || filters.iter().any(|p| p.is_match(relative)))
+                && !excludes.iter().any(|p| p.is_match(relative))
+        })
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,146 @@
+use super::suite::ElmCompilerPath;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A hash of the inputs to compiling or running a suite.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint(String);
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Fields are length prefixed so that moving bytes from one field to the
+/// next changes the fingerprint.
+pub struct Hasher(Sha256);
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self(Sha256::new())
+    }
+
+    pub fn field(&mut self, name: &str, contents: Option<&[u8]>) {
+        self.0.update((name.len() as u64).to_le_bytes());
+        self.0.update(name);
+        if let Some(contents) = contents {
+            self.0.update([1]);
+            self.0.update((contents.len() as u64).to_le_bytes());
+            self.0.update(contents);
+        } else {
+            self.0.update([0]);
+        }
+    }
+
+    /// Hash the compiler binary and everything its definition adds to the
+    /// command that invokes it.
+    pub fn compiler(
+        &mut self,
+        hashes: &CompilerHashes,
+        compiler: &ElmCompilerPath,
+    ) -> io::Result<()> {
+        self.field("compiler", Some(&hashes.get(compiler.path())?));
+        self.field("compiler-args", Some(compiler.args().join("\0").as_bytes()));
+        self.field(
+            "compiler-env",
+            Some(
+                compiler
+                    .env()
+                    .iter()
+                    .map(|(name, value)| format!("{name}={value}"))
+                    .collect::<Vec<_>>()
+                    .join("\0")
+                    .as_bytes(),
+            ),
+        );
+        self.field(
+            "compiler-elm-home",
+            compiler
+                .elm_home()
+                .map(|elm_home| elm_home.to_string_lossy().into_owned().into_bytes())
+                .as_deref(),
+        );
+        Ok(())
+    }
+
+    /// Hash the contents of `path`, which may not exist.
+    pub fn optional_file(&mut self, name: &str, path: &Path) -> io::Result<()> {
+        match fs::read(path) {
+            Ok(contents) => self.field(name, Some(&contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => self.field(name, None),
+            Err(e) => return Err(e),
+        }
+        Ok(())
+    }
+
+    /// Hash every file in `dir` (apart from those in elm-stuff) in a stable
+    /// order.
+    pub fn dir(&mut self, name: &str, dir: &Path) -> io::Result<()> {
+        let mut entries = match fs::read_dir(dir) {
+            Ok(entries) => entries.collect::<Result<Vec<_>, _>>()?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.field(name, None);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        entries.sort_by_key(fs::DirEntry::file_name);
+        for entry in entries {
+            if entry.file_name() == "elm-stuff" {
+                continue;
+            }
+            let name = format!("{}/{}", name, entry.file_name().to_string_lossy());
+            if entry.file_type()?.is_dir() {
+                self.dir(&name, &entry.path())?;
+            } else {
+                self.field(&name, Some(&fs::read(entry.path())?));
+            }
+        }
+        Ok(())
+    }
+
+    /// Hash the source directories listed in the elm.json of `suite`.
+    pub fn source_dirs(&mut self, suite: &Path, elm_json: &[u8]) -> io::Result<()> {
+        let elm_json: serde_json::Value = serde_json::from_slice(elm_json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let source_dirs = match elm_json.get("source-directories") {
+            Some(serde_json::Value::Array(dirs)) => dirs
+                .iter()
+                .filter_map(serde_json::Value::as_str)
+                .collect::<Vec<_>>(),
+            // Packages do not list their source directory.
+            _ => vec!["src"],
+        };
+        for dir in source_dirs {
+            self.dir(dir, &suite.join(dir))?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> Fingerprint {
+        Fingerprint(format!("{:x}", self.0.finalize()))
+    }
+}
+
+/// Hashes of compiler binaries. Compilers are large so each is only hashed
+/// once per run of elm-torture.
+#[derive(Default)]
+pub struct CompilerHashes(Mutex<HashMap<PathBuf, Box<[u8]>>>);
+
+impl CompilerHashes {
+    pub fn get(&self, compiler: &Path) -> io::Result<Box<[u8]>> {
+        let mut hashes = self.0.lock().unwrap();
+        if let Some(hash) = hashes.get(compiler) {
+            return Ok(hash.clone());
+        }
+        let hash: Box<[u8]> = Sha256::digest(fs::read(compiler)?).as_slice().into();
+        hashes.insert(compiler.to_path_buf(), hash.clone());
+        Ok(hash)
+    }
+}
@@ -1,9 +1,16 @@
 #![allow(clippy::enum_glob_use)]
 
+use super::baseline;
+use super::compile_cache;
+use super::config_layers;
+use super::diff;
 use super::find_suites;
 use super::suite;
 use super::suite::CompileAndRunError;
 use super::suite::GetSuiteConfigError;
+use super::suite::InfrastructureError;
+use colored::{ColoredString, Colorize};
+use std::cmp;
 use std::fmt;
 use std::path::Path;
 use std::process;
@@ -20,6 +27,25 @@ pub fn easy_format<F: Fn(&mut fmt::Formatter<'_>) -> fmt::Result>(func: F) -> im
     Formatable { func }
 }
 
+/// The tags of a suite, ready to follow its name.
+pub fn tags(tags: &[String]) -> impl fmt::Display + '_ {
+    easy_format(move |f| {
+        if tags.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " [{}]", tags.join(", ").dimmed())
+        }
+    })
+}
+
+/// Render without ANSI colour codes (for example when writing report files).
+pub fn without_colors<T>(f: impl FnOnce() -> T) -> T {
+    colored::control::set_override(false);
+    let result = f();
+    colored::control::unset_override();
+    result
+}
+
 fn process_output(output: &process::Output) -> impl fmt::Display + '_ {
     easy_format(move |f| {
         write!(
@@ -57,6 +83,15 @@ fn process_stderr(stderr: &[u8]) -> impl fmt::Display + '_ {
     })
 }
 
+/// Notes where a limit came from when the suite overrode the configured one.
+fn limit_source<T>(limit: &suite::Limit<T>) -> &'static str {
+    if limit.set_by_suite {
+        " (set by the suite's output.json)"
+    } else {
+        ""
+    }
+}
+
 fn compiler_error<'a>(
     err: &'a suite::CompileError,
     suite: impl AsRef<Path> + 'a,
@@ -70,18 +105,74 @@ fn compiler_error<'a>(
                 suite.as_ref().display(),
                 err
             ),
-            Process(err) => panic!("Failed to execute compiler! Details:\n{}", err),
+            CopyingSuite(err) => write!(
+                f,
+                "Could not copy suite {} into the out dir to compile it. Details:\n{}",
+                suite.as_ref().display(),
+                err
+            ),
+            SeedingElmHome(err) => write!(
+                f,
+                "Could not copy your ELM_HOME (or the elm-home of the compiler definition) into the ELM_HOME used by the compiler. Details:\n{err}"
+            ),
+            Process(err) => write!(f, "Failed to execute the compiler! Details:\n{err}"),
             Compiler(output) | CompilerStdErrNotEmpty(output) => {
                 write!(f, "Compilation failed!\n{}", process_output(&output))
             }
             SuiteDoesNotExist => {
                 panic!("Path was not suite - this should have been checked already!")
             }
-            DeletingElmStuff(e) => panic!("Could not delete elm-stuff directory! Details: {}", e),
+            DeletingElmStuff(e) => write!(
+                f,
+                "Could not delete the elm-stuff directory of suite {}! Details:\n{}",
+                suite.as_ref().display(),
+                e
+            ),
+            Timeout {
+                after,
+                stdout,
+                stderr,
+            } => write!(
+                f,
+                "Compilation was stopped after {}{}.\n{}\n{}",
+                humantime::format_duration(after.value),
+                limit_source(after),
+                process_stdout(stdout),
+                process_stderr(stderr)
+            ),
+        }
+    })
+}
+
+fn logs_diff<'a>(expected: &'a str, actual: &'a str) -> impl fmt::Display + 'a {
+    easy_format(move |f| {
+        let hunks = diff::hunks(&diff::line_diff(expected, actual), 3);
+        if hunks.is_empty() {
+            writeln!(f, "  (the logs only differ in line endings)")?;
+        }
+        for hunk in hunks {
+            writeln!(
+                f,
+                "  {}",
+                format!(
+                    "@@ -{},{} +{},{} @@",
+                    hunk.expected_start, hunk.expected_len, hunk.actual_start, hunk.actual_len
+                )
+                .cyan()
+            )?;
+            for line in hunk.lines {
+                match line {
+                    diff::Line::Both(l) => writeln!(f, "   {l}")?,
+                    diff::Line::Expected(l) => writeln!(f, "  {}", format!("-{l}").green())?,
+                    diff::Line::Actual(l) => writeln!(f, "  {}", format!("+{l}").red())?,
+                }
+            }
         }
+        Ok(())
     })
 }
 
+#[allow(clippy::too_many_lines)]
 fn run_error<'a>(err: &'a suite::RunError, out_dir: &'a Path) -> impl fmt::Display + 'a {
     easy_format(move |f| {
         use suite::RunError::*;
@@ -94,15 +185,19 @@ fn run_error<'a>(err: &'a suite::RunError, out_dir: &'a Path) -> impl fmt::Displ
             SuiteDoesNotExist => {
                 panic!("Path was not suite - this should have been checked already!")
             }
-            NodeProcess(err) => panic!("The node process errored unexpectedly:\n{}", err),
-            WritingHarness(err) => panic!(
-                "Cannot add the test harness to the output directory. Details:\n{}",
+            NodeProcess(err) => write!(f, "The node process errored unexpectedly:\n{err}"),
+            WritingHarness(err) => write!(
+                f,
+                "Cannot add the test harness to the output directory {}. Details:\n{}",
+                out_dir.display(),
                 err
             ),
-            ExpectedOutputNotUtf8(_) => panic!("Expected output is not valid utf8"),
-            CopyingExpectedOutput(err) => panic!(
-                "The expected output exists but cannot be copied. Details:\n{}",
-                err
+            ExpectedOutputNotUtf8(err) => {
+                write!(f, "Expected output is not valid utf8. Details:\n{err}")
+            }
+            CopyingExpectedOutput(err) => write!(
+                f,
+                "The expected output exists but cannot be copied. Details:\n{err}"
             ),
             Runtime(output) => {
                 write!(f, "{}", process_output(&output))?;
@@ -113,6 +208,50 @@ fn run_error<'a>(err: &'a suite::RunError, out_dir: &'a Path) -> impl fmt::Displ
                 )
             }
 
+            PortMismatch {
+                port,
+                event_index,
+                expected,
+                actual,
+                ..
+            } => {
+                writeln!(
+                    f,
+                    "Wrong data sent to port {} during port event {}:",
+                    port.bold(),
+                    event_index
+                )?;
+                for diff::JsonDifference {
+                    path,
+                    expected,
+                    actual,
+                } in diff::json_differences(expected, actual)
+                {
+                    let show = |value: Option<&serde_json::Value>| {
+                        value.map_or_else(|| "(missing)".to_string(), ToString::to_string)
+                    };
+                    writeln!(f, "  at {}", path.cyan())?;
+                    writeln!(f, "    expected: {}", show(expected).green())?;
+                    writeln!(f, "    actual:   {}", show(actual).red())?;
+                }
+                write!(
+                    f,
+                    "\nTo inspect the built files that caused this error see:\n  {}",
+                    out_dir.display()
+                )
+            }
+
+            LogsMismatch {
+                expected, actual, ..
+            } => write!(
+                f,
+                "Unexpected Debug.log output ({}, {}):\n{}\nTo inspect the built files that caused this error see:\n  {}",
+                "-expected".green(),
+                "+actual".red(),
+                logs_diff(expected, actual),
+                out_dir.display()
+            ),
+
             OutputProduced(output) => write!(
                 f,
                 "The suite ran without error but produced the following output!:\n{}",
@@ -124,10 +263,11 @@ fn run_error<'a>(err: &'a suite::RunError, out_dir: &'a Path) -> impl fmt::Displ
                 stderr,
             } => write!(
                 f,
-                "Running of the suite was stopped after {}.{}
+                "Running of the suite was stopped after {}{}.{}
 
 To inspect the built files that caused this error see: {}",
-                humantime::format_duration(*after),
+                humantime::format_duration(after.value),
+                limit_source(after),
                 easy_format(|f| {
                     if !stdout.is_empty() || !stderr.is_empty() {
                         write!(
@@ -144,14 +284,17 @@ To inspect the built files that caused this error see: {}",
                 }),
                 out_dir.display()
             ),
-            WritingExpectedOutput(err) => panic!(
-                "Error whilst writing expected output to disk. Details:\n{}",
+            WritingExpectedOutput(err) => write!(
+                f,
+                "Error whilst writing expected output to {}. Details:\n{}",
+                out_dir.display(),
                 err
             ),
         }
     })
 }
 
+#[allow(clippy::too_many_lines)]
 pub fn compile_and_run_error<'a, Pe: AsRef<Path> + 'a, Ps: AsRef<Path> + 'a>(
     err: &'a CompileAndRunError,
     suite: Ps,
@@ -204,12 +347,18 @@ pub fn compile_and_run_error<'a, Pe: AsRef<Path> + 'a, Ps: AsRef<Path> + 'a>(
                 error
             ),
 
-            CompileFailure { allowed, reason } => {
+            CompileFailure {
+                allowed,
+                max_retries,
+                reason,
+            } => {
                 write!(
                     f,
-                    "Failed to compile suite {} after {} retries.\n{}\n",
+                    "Failed to compile suite {} after {} retries (at most {}{}).\n{}\n",
                     &suite.as_ref().display(),
                     retries,
+                    max_retries.value,
+                    limit_source(max_retries),
                     indented::indented(compiler_error(&reason, &suite))
                 )?;
                 if *allowed {
@@ -233,6 +382,24 @@ pub fn compile_and_run_error<'a, Pe: AsRef<Path> + 'a, Ps: AsRef<Path> + 'a>(
                 }
             }
 
+            Infrastructure(reason) => {
+                writeln!(
+                    f,
+                    "elm-torture could not test suite {} because of a problem with the machine it is running on (rather than with the suite or the compiler).",
+                    &suite.as_ref().display(),
+                )?;
+                match reason {
+                    InfrastructureError::Compile(reason) => {
+                        write!(f, "{}", indented::indented(compiler_error(reason, &suite)))
+                    }
+                    InfrastructureError::Run(reason) => write!(
+                        f,
+                        "{}",
+                        indented::indented(run_error(reason, out_dir.as_ref()))
+                    ),
+                }
+            }
+
             ExpectedCompileFailure => write!(
                 f,
                 "elm-torture expected a failure when compiling suite {}",
@@ -249,6 +416,311 @@ pub fn compile_and_run_error<'a, Pe: AsRef<Path> + 'a, Ps: AsRef<Path> + 'a>(
     })
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatrixCell {
+    Pass,
+    CachedPass,
+    AllowedFailure,
+    UnexpectedPass,
+    Failure,
+    SkippedRun,
+}
+
+impl MatrixCell {
+    const ALL: [Self; 6] = [
+        Self::Pass,
+        Self::CachedPass,
+        Self::AllowedFailure,
+        Self::UnexpectedPass,
+        Self::Failure,
+        Self::SkippedRun,
+    ];
+
+    fn new(result: &suite::SscceRunResult) -> Self {
+        use suite::Outcome;
+        match suite::Outcome::of(result.error.as_ref()) {
+            Outcome::Success if result.cached_pass => Self::CachedPass,
+            Outcome::Success if result.run_skipped => Self::SkippedRun,
+            Outcome::Success => Self::Pass,
+            Outcome::AllowedCompileFailure | Outcome::AllowedRunFailure => Self::AllowedFailure,
+            Outcome::ExpectedCompileFailure | Outcome::ExpectedRunFailure => Self::UnexpectedPass,
+            Outcome::CompileFailure
+            | Outcome::RunFailure
+            | Outcome::SuiteError
+            | Outcome::InfrastructureError => Self::Failure,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::Pass => "pass",
+            Self::CachedPass => "cached",
+            Self::AllowedFailure => "allowed",
+            Self::UnexpectedPass => "unexpected",
+            Self::Failure => "FAIL",
+            Self::SkippedRun => "skip-run",
+        }
+    }
+
+    fn colorize(self, padded: &str) -> ColoredString {
+        match self {
+            Self::Pass | Self::CachedPass => padded.green(),
+            Self::AllowedFailure | Self::SkippedRun => padded.yellow(),
+            Self::UnexpectedPass | Self::Failure => padded.red(),
+        }
+    }
+}
+
+/// A table with a row per suite and a column per compiler and optimisation
+/// level, followed by the number of each kind of result in every column.
+pub fn result_matrix<Ps: AsRef<Path>>(
+    results: &[suite::CompileAndRunResults<Ps>],
+) -> impl fmt::Display + '_ {
+    easy_format(move |f| {
+        let mut columns = results
+            .iter()
+            .flat_map(|r| r.errors.keys())
+            .collect::<Vec<_>>();
+        columns.sort_by_key(|(compiler, opt_level)| (compiler.to_string(), *opt_level));
+        columns.dedup();
+
+        let headers = columns
+            .iter()
+            .map(|(compiler, opt_level)| format!("{}/{}", compiler, opt_level.id()))
+            .collect::<Vec<_>>();
+        let widths = headers
+            .iter()
+            .map(|h| {
+                MatrixCell::ALL
+                    .iter()
+                    .map(|c| c.label().len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(h.len())
+            })
+            .collect::<Vec<_>>();
+        let names = results
+            .iter()
+            .map(|r| r.suite.as_ref().display().to_string())
+            .collect::<Vec<_>>();
+        let name_width = names
+            .iter()
+            .map(String::len)
+            .chain(MatrixCell::ALL.iter().map(|c| c.label().len()))
+            .max()
+            .unwrap_or(0);
+
+        write!(f, "{:width$}", "", width = name_width)?;
+        for (header, width) in headers.iter().zip(&widths) {
+            write!(f, "  {header:width$}")?;
+        }
+        writeln!(f)?;
+
+        let mut totals = vec![[0_usize; MatrixCell::ALL.len()]; columns.len()];
+        for (name, result) in names.iter().zip(results) {
+            write!(f, "{name:name_width$}")?;
+            for ((column, width), total) in columns.iter().zip(&widths).zip(&mut totals) {
+                if let Some(run_result) = result.errors.get(column) {
+                    let cell = MatrixCell::new(run_result);
+                    total[MatrixCell::ALL.iter().position(|c| *c == cell).unwrap()] += 1;
+                    let padded = format!("{:width$}", cell.label(), width = width);
+                    write!(f, "  {}", cell.colorize(&padded))?;
+                } else {
+                    write!(f, "  {:width$}", "-", width = width)?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f)?;
+        for (i, cell) in MatrixCell::ALL.iter().enumerate() {
+            write!(f, "{:width$}", cell.label(), width = name_width)?;
+            for (total, width) in totals.iter().zip(&widths) {
+                write!(f, "  {:<width$}", total[i], width = width)?;
+            }
+            writeln!(f)?;
+        }
+        writeln!(
+            f,
+            "\n(cached: passed in a previous --incremental run, unexpected: passed when elm-torture expected a failure, skip-run: compiled but not run due to skip-run-if)"
+        )
+    })
+}
+
+/// Where each configuration value comes from, for the suites directory and
+/// then for each subdirectory that has its own config file.
+pub fn explain_config(layers: &config_layers::Layers) -> impl fmt::Display + '_ {
+    easy_format(move |f| {
+        let write_settings = |f: &mut fmt::Formatter<'_>,
+                              settings: &[&(&str, Option<String>, config_layers::Source)]|
+         -> fmt::Result {
+            let show = |value: &Option<String>| value.clone().unwrap_or_else(|| "-".to_string());
+            let name_width = settings
+                .iter()
+                .map(|(name, ..)| name.len())
+                .max()
+                .unwrap_or(0);
+            let value_width = settings
+                .iter()
+                .map(|(_, value, _)| show(value).len())
+                .max()
+                .unwrap_or(0);
+            for (name, value, source) in settings {
+                let source = match source {
+                    config_layers::Source::Environment => config_layers::env_var(name),
+                    _ => source.to_string(),
+                };
+                writeln!(
+                    f,
+                    "    {:name_width$}  {:value_width$}  {}",
+                    name,
+                    show(value),
+                    source.dimmed(),
+                    name_width = name_width,
+                    value_width = value_width
+                )?;
+            }
+            Ok(())
+        };
+
+        let root = layers.explain(layers.suites_dir());
+        writeln!(
+            f,
+            "Configuration of the suites in {}:",
+            layers.suites_dir().display()
+        )?;
+        write_settings(f, &root.iter().collect::<Vec<_>>())?;
+        for dir in layers.nested_dirs() {
+            let nested = layers.explain(dir);
+            let overridden = nested
+                .iter()
+                .zip(&root)
+                .filter(|(nested, root)| nested != root)
+                .map(|(nested, _)| nested)
+                .collect::<Vec<_>>();
+            writeln!(f, "\nOverrides for the suites in {}:", dir.display())?;
+            if overridden.is_empty() {
+                writeln!(f, "    (none)")?;
+            } else {
+                write_settings(f, &overridden)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+pub fn cache_stats<'a, Ps: AsRef<Path>>(
+    results: &'a [suite::CompileAndRunResults<Ps>],
+    cache_dir: &'a Path,
+) -> impl fmt::Display + 'a {
+    easy_format(move |f| {
+        let lookups = results
+            .iter()
+            .flat_map(|result| result.errors.values())
+            .filter_map(|result| result.cache_lookup);
+        let (hits, misses) = lookups.fold((0, 0), |(hits, misses), lookup| match lookup {
+            compile_cache::Lookup::Hit => (hits + 1, misses),
+            compile_cache::Lookup::Miss => (hits, misses + 1),
+        });
+        write!(
+            f,
+            "Compile cache: {} hit{}, {} miss{} (cached in {})",
+            hits,
+            if hits == 1 { "" } else { "s" },
+            misses,
+            if misses == 1 { "" } else { "es" },
+            cache_dir.display()
+        )
+    })
+}
+
+pub fn slowest_suites<Ps: AsRef<Path>>(
+    results: &[suite::CompileAndRunResults<Ps>],
+    count: usize,
+) -> impl fmt::Display + '_ {
+    easy_format(move |f| {
+        let mut totals = results
+            .iter()
+            .map(|suite::CompileAndRunResults { suite, errors, .. }| {
+                let mut total = suite::Timings::default();
+                for suite::SscceRunResult { timings, .. } in errors.values() {
+                    total.delete_elm_stuff += timings.delete_elm_stuff;
+                    total.compile.push(timings.total_compile());
+                    if let Some(run) = timings.run {
+                        total.run = Some(total.run.unwrap_or_default() + run);
+                    }
+                }
+                (suite.as_ref().display().to_string(), total)
+            })
+            .collect::<Vec<_>>();
+        totals.sort_by_key(|(_, timings)| cmp::Reverse(timings.total()));
+        totals.truncate(count);
+
+        let width = totals
+            .iter()
+            .map(|(name, _)| name.len())
+            .max()
+            .unwrap_or(0)
+            .max("SSCCE".len());
+        writeln!(
+            f,
+            "The {} slowest SSCCE{} (summed over all compilers and optimisation levels):",
+            totals.len(),
+            if totals.len() == 1 { "" } else { "s" },
+        )?;
+        writeln!(
+            f,
+            "    {:width$}  {:>10}  {:>10}  {:>10}  {:>10}",
+            "SSCCE",
+            "elm-stuff",
+            "compile",
+            "run",
+            "total",
+            width = width
+        )?;
+        for (name, timings) in &totals {
+            writeln!(
+                f,
+                "    {:width$}  {:>9.2}s  {:>9.2}s  {:>9.2}s  {:>9.2}s",
+                name,
+                timings.delete_elm_stuff.as_secs_f64(),
+                timings.total_compile().as_secs_f64(),
+                timings.run.unwrap_or_default().as_secs_f64(),
+                timings.total().as_secs_f64(),
+                width = width
+            )?;
+        }
+        Ok(())
+    })
+}
+
+pub fn baseline_comparison(comparison: &baseline::Comparison) -> impl fmt::Display + '_ {
+    easy_format(move |f| {
+        let section = |f: &mut fmt::Formatter<'_>, title, changes: &[baseline::Change]| {
+            writeln!(f, "    {} ({}):", title, changes.len())?;
+            for baseline::Change { key, before, after } in changes {
+                write!(
+                    f,
+                    "        {} compiling with {} in {} optimisation mode ",
+                    key.suite.display(),
+                    key.compiler,
+                    key.opt_level,
+                )?;
+                match before {
+                    Some(before) if before == after => writeln!(f, "({after})")?,
+                    Some(before) => writeln!(f, "({before} -> {after})")?,
+                    None => writeln!(f, "(not in baseline -> {after})")?,
+                }
+            }
+            Ok(())
+        };
+        writeln!(f, "Changes since the baseline:")?;
+        section(f, "Newly failing", &comparison.newly_failing)?;
+        section(f, "Newly passing", &comparison.newly_passing)?;
+        section(f, "Still failing", &comparison.still_failing)
+    })
+}
+
 pub fn find_suite_error<'a>(
     err: &'a find_suites::Error,
     suite_dir: &'a Path,
@@ -269,11 +741,53 @@ pub fn find_suite_error<'a>(
 }
 
 pub fn suites_error(err: &suite::SuitesError) -> impl fmt::Display + '_ {
-    use suite::SuitesError;
-    easy_format(move |_| match err {
-        SuitesError::ResolvingCompiler(e) => panic!("Could not resolve the elm compiler {:?}", e), // SuitesError::CannotDetectStdlibVariant(e) => {
-                                                                                                   //     panic!("Failed to detect stdlib variant due to error: {:?}", e)
-                                                                                                   // }
+    use suite::{DetectStdlibError, SuitesError};
+    easy_format(move |f| {
+        match err {
+            SuitesError::ResolvingCompiler {
+                compiler,
+                error: DetectStdlibError::LocatingCompiler(e),
+            } => write!(
+                f,
+                "Could not find the elm compiler {}. Please check it is on your PATH. Details:\n{}",
+                compiler.bold(),
+                e
+            ),
+            SuitesError::ResolvingCompiler {
+                compiler,
+                error: DetectStdlibError::Io(e),
+            } => write!(
+                f,
+                "Could not run the elm compiler {} to detect its stdlib variant. Details:\n{}",
+                compiler.bold(),
+                e
+            ),
+            SuitesError::ResolvingCompiler {
+                compiler,
+                error: DetectStdlibError::Parsing(stdout),
+            } => write!(
+                f,
+                "Could not understand the output of `{} --stdlib-variant` when detecting the stdlib variant of the compiler:\n{}",
+                compiler.bold(),
+                String::from_utf8_lossy(stdout)
+            ),
+            SuitesError::CreatingElmHomes(e) => write!(
+                f,
+                "Could not create the temporary directory for the ELM_HOMEs used by the compiler. Details:\n{e}"
+            ),
+            SuitesError::CompilerDirClash { first, second, .. } if first == second => write!(
+                f,
+                "More than one elm compiler is called {}. Please give them different names.",
+                first.bold()
+            ),
+            SuitesError::CompilerDirClash { first, second, dir } => write!(
+                f,
+                "The elm compilers {} and {} would both put their built files in directories called {}. Please give them more different names.",
+                first.bold(),
+                second.bold(),
+                dir
+            ),
+        }
     })
 }
 
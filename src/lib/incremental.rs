@@ -0,0 +1,90 @@
+use super::config::{Config, OptimizationLevel};
+use super::fingerprint::{CompilerHashes, Fingerprint, Hasher};
+use super::suite::{self, ElmCompilerPath};
+use log::debug;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+
+/// Records which combinations of suite, compiler and optimisation level have
+/// passed, keyed by a fingerprint of everything that could change the result:
+/// the files of the suite (including its source directories and
+/// output.json), the compiler, the optimisation level, the settings that
+/// apply to the suite, node, the test harness and the version of elm-torture.
+pub struct Passes {
+    dir: PathBuf,
+    compiler_hashes: Arc<CompilerHashes>,
+    /// The resolved path to node and its version. None if `node --version`
+    /// failed, in which case nothing is skipped.
+    node: Option<(PathBuf, Vec<u8>)>,
+}
+
+impl Passes {
+    pub fn new(dir: PathBuf, compiler_hashes: Arc<CompilerHashes>, node: &str) -> Self {
+        let node = match which::which(node) {
+            Ok(path) => match Command::new(&path).arg("--version").output() {
+                Ok(output) if output.status.success() => Some((path, output.stdout)),
+                Ok(output) => {
+                    debug!("`{node} --version` failed: {output:?}");
+                    None
+                }
+                Err(e) => {
+                    debug!("Could not run `{node} --version`: {e}");
+                    None
+                }
+            },
+            Err(e) => {
+                debug!("Could not find {node}: {e}");
+                None
+            }
+        };
+        Self {
+            dir,
+            compiler_hashes,
+            node,
+        }
+    }
+
+    pub fn fingerprint(
+        &self,
+        suite: &Path,
+        compiler: &ElmCompilerPath,
+        opt_level: OptimizationLevel,
+        config: &Config,
+    ) -> io::Result<Fingerprint> {
+        let (node_path, node_version) = self
+            .node
+            .as_ref()
+            .ok_or_else(|| io::Error::other("the version of node is unknown"))?;
+        let mut hasher = Hasher::new();
+        hasher.field("elm-torture", Some(env!("CARGO_PKG_VERSION").as_bytes()));
+        hasher.field("harness", Some(suite::HARNESS_JS));
+        hasher.field("xmlhttprequest", Some(suite::XML_HTTP_REQUEST_JS));
+        hasher.field("node-path", Some(node_path.to_string_lossy().as_bytes()));
+        hasher.field("node", Some(node_version));
+        for (name, value) in config.per_suite_settings() {
+            hasher.field(name, value.as_deref().map(str::as_bytes));
+        }
+        hasher.compiler(&self.compiler_hashes, compiler)?;
+        hasher.field("opt-level", Some(opt_level.id().as_bytes()));
+        hasher.dir("suite", suite)?;
+        hasher.source_dirs(suite, &fs::read(suite.join("elm.json"))?)?;
+        Ok(hasher.finish())
+    }
+
+    fn path(&self, fingerprint: &Fingerprint) -> PathBuf {
+        self.dir.join(fingerprint.to_string())
+    }
+
+    pub fn passed(&self, fingerprint: &Fingerprint) -> bool {
+        self.path(fingerprint).exists()
+    }
+
+    pub fn record(&self, fingerprint: &Fingerprint) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        fs::File::create(self.path(fingerprint))?;
+        Ok(())
+    }
+}
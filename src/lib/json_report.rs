@@ -0,0 +1,288 @@
+use super::compile_cache;
+use super::config::OptimizationLevel;
+use super::formatting;
+use super::suite::{
+    CompileAndRunError, CompileAndRunResults, CompileError, Outcome, RunError, SscceRunResult,
+    StdlibVariant,
+};
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::iter::FromIterator;
+use std::path::{Path, PathBuf};
+use std::process::Output;
+use std::time::Duration;
+
+/// Bumped whenever a field is removed or changes meaning. Adding fields does
+/// not change the version. See `json-report.md` for the schema.
+pub const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Report<'a> {
+    schema_version: u32,
+    suites: Vec<SuiteReport<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct SuiteReport<'a> {
+    suite: &'a Path,
+    tags: &'a [String],
+    out_dir: &'a Path,
+    runs: Vec<RunReport<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct CompilerReport<'a> {
+    name: &'a str,
+    binary: &'a str,
+    path: &'a Path,
+    stdlib_variant: StdlibVariant,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct RunReport<'a> {
+    compiler: CompilerReport<'a>,
+    opt_level: OptimizationLevel,
+    out_dir: &'a Path,
+    retries: usize,
+    timings: TimingsReport,
+    outcome: Outcome,
+    run_skipped: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compile_cache: Option<compile_cache::Lookup>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    cached_pass: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    process_output: Option<ProcessOutput<'a>>,
+}
+
+/// Durations in seconds.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct TimingsReport {
+    delete_elm_stuff: f64,
+    /// One entry per compilation attempt.
+    compile: Vec<f64>,
+    run: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProcessOutput<'a> {
+    /// `None` if the process was killed (by a signal or by elm-torture).
+    exit_code: Option<i32>,
+    stdout: Cow<'a, str>,
+    stderr: Cow<'a, str>,
+}
+
+impl<'a> ProcessOutput<'a> {
+    fn new(exit_code: Option<i32>, stdout: &'a [u8], stderr: &'a [u8]) -> Self {
+        Self {
+            exit_code,
+            stdout: String::from_utf8_lossy(stdout),
+            stderr: String::from_utf8_lossy(stderr),
+        }
+    }
+
+    fn from_output(output: &'a Output) -> Self {
+        Self::new(output.status.code(), &output.stdout, &output.stderr)
+    }
+}
+
+fn process_output(error: &CompileAndRunError) -> Option<ProcessOutput<'_>> {
+    match error {
+        CompileAndRunError::CompileFailure {
+            reason: CompileError::Compiler(output) | CompileError::CompilerStdErrNotEmpty(output),
+            ..
+        }
+        | CompileAndRunError::RunFailure {
+            reason:
+                RunError::Runtime(output)
+                | RunError::OutputProduced(output)
+                | RunError::PortMismatch { output, .. }
+                | RunError::LogsMismatch { output, .. },
+            ..
+        } => Some(ProcessOutput::from_output(output)),
+        CompileAndRunError::CompileFailure {
+            reason: CompileError::Timeout { stdout, stderr, .. },
+            ..
+        }
+        | CompileAndRunError::RunFailure {
+            reason: RunError::Timeout { stdout, stderr, .. },
+            ..
+        } => Some(ProcessOutput::new(None, stdout, stderr)),
+        _ => None,
+    }
+}
+
+pub fn report<Ps: AsRef<Path>>(results: &[CompileAndRunResults<Ps>]) -> Report<'_> {
+    let suites = results
+        .iter()
+        .map(
+            |CompileAndRunResults {
+                 suite,
+                 tags,
+                 sscce_out_dir,
+                 errors,
+             }| {
+                let mut runs = errors
+                    .iter()
+                    .map(
+                        |(
+                            (compiler, opt_level),
+                            SscceRunResult {
+                                out_dir,
+                                retries,
+                                timings,
+                                run_skipped,
+                                cache_lookup,
+                                cached_pass,
+                                error,
+                            },
+                        )| RunReport {
+                            compiler: CompilerReport {
+                                name: compiler.name(),
+                                binary: compiler.binary(),
+                                path: compiler.path(),
+                                stdlib_variant: compiler.stdlib_variant,
+                            },
+                            opt_level: *opt_level,
+                            out_dir,
+                            retries: *retries,
+                            timings: TimingsReport {
+                                delete_elm_stuff: timings.delete_elm_stuff.as_secs_f64(),
+                                compile: timings
+                                    .compile
+                                    .iter()
+                                    .map(Duration::as_secs_f64)
+                                    .collect(),
+                                run: timings.run.as_ref().map(Duration::as_secs_f64),
+                            },
+                            outcome: Outcome::of(error.as_ref()),
+                            run_skipped: *run_skipped,
+                            compile_cache: *cache_lookup,
+                            cached_pass: *cached_pass,
+                            message: error.as_ref().map(|e| {
+                                formatting::compile_and_run_error(e, suite, out_dir, *retries)
+                                    .to_string()
+                            }),
+                            process_output: error.as_ref().and_then(process_output),
+                        },
+                    )
+                    .collect::<Vec<_>>();
+                runs.sort_by_key(|run| (run.compiler.name, run.opt_level.id()));
+                SuiteReport {
+                    suite: suite.as_ref(),
+                    tags,
+                    out_dir: sscce_out_dir,
+                    runs,
+                }
+            },
+        )
+        .collect();
+    Report {
+        schema_version: SCHEMA_VERSION,
+        suites,
+    }
+}
+
+pub fn write_report<Ps: AsRef<Path>>(
+    path: impl AsRef<Path>,
+    results: &[CompileAndRunResults<Ps>],
+) -> io::Result<()> {
+    let mut file = File::create(path)?;
+    formatting::without_colors(|| serde_json::to_writer_pretty(&mut file, &report(results)))?;
+    writeln!(&mut file)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ReportFile {
+    schema_version: u32,
+    suites: Vec<SuiteDurationsFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct SuiteDurationsFile {
+    suite: PathBuf,
+    runs: Vec<RunDurationsFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct RunDurationsFile {
+    timings: TimingsFile,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct TimingsFile {
+    delete_elm_stuff: f64,
+    compile: Vec<f64>,
+    run: Option<f64>,
+}
+
+/// How long each suite took (summed over all compilers and optimisation
+/// levels) according to a previously written report.
+#[derive(Debug, Default)]
+pub struct SuiteDurations(HashMap<PathBuf, Duration>);
+
+impl SuiteDurations {
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .map_err(|e| format!("JSON report {} not found: {}", path.display(), e))?;
+        let ReportFile {
+            schema_version,
+            suites,
+        } = serde_json::from_reader(file).map_err(|e| {
+            format!(
+                "Could not parse file {} as a JSON report: {}",
+                path.display(),
+                e
+            )
+        })?;
+        // The timings have not changed since version 1.
+        if !(1..=SCHEMA_VERSION).contains(&schema_version) {
+            return Err(format!(
+                "JSON report {} has version {} but elm-torture only understands versions 1 to {}",
+                path.display(),
+                schema_version,
+                SCHEMA_VERSION
+            ));
+        }
+        Ok(suites
+            .into_iter()
+            .map(|SuiteDurationsFile { suite, runs }| {
+                let seconds = runs
+                    .iter()
+                    .map(|RunDurationsFile { timings }| {
+                        timings.delete_elm_stuff
+                            + timings.compile.iter().sum::<f64>()
+                            + timings.run.unwrap_or_default()
+                    })
+                    .sum::<f64>();
+                (suite, Duration::from_secs_f64(seconds.max(0.0)))
+            })
+            .collect())
+    }
+
+    pub fn get(&self, suite: &Path) -> Option<Duration> {
+        self.0.get(suite).copied()
+    }
+}
+
+impl FromIterator<(PathBuf, Duration)> for SuiteDurations {
+    fn from_iter<I: IntoIterator<Item = (PathBuf, Duration)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
@@ -0,0 +1,192 @@
+use super::formatting;
+use super::suite::{CompileAndRunResults, Outcome, SscceRunResult};
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // XML 1.0 cannot represent most control characters, even escaped.
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[derive(Default)]
+struct Counts {
+    tests: usize,
+    failures: usize,
+    errors: usize,
+    skipped: usize,
+}
+
+impl Counts {
+    fn attributes(&self) -> String {
+        format!(
+            r#"tests="{}" failures="{}" errors="{}" skipped="{}""#,
+            self.tests, self.failures, self.errors, self.skipped
+        )
+    }
+}
+
+enum TestCaseResult {
+    Passed,
+    Skipped(&'static str),
+    Failure(&'static str),
+    Error(&'static str),
+}
+
+fn test_case_result(outcome: Outcome) -> TestCaseResult {
+    match outcome {
+        Outcome::Success => TestCaseResult::Passed,
+        Outcome::AllowedCompileFailure => TestCaseResult::Skipped("allowed compile failure"),
+        Outcome::AllowedRunFailure => TestCaseResult::Skipped("allowed run failure"),
+        Outcome::CompileFailure => TestCaseResult::Failure("compile failure"),
+        Outcome::RunFailure => TestCaseResult::Failure("run failure"),
+        Outcome::ExpectedCompileFailure => {
+            TestCaseResult::Failure("success when elm-torture expected a compile time failure")
+        }
+        Outcome::ExpectedRunFailure => {
+            TestCaseResult::Failure("success when elm-torture expected a run time failure")
+        }
+        Outcome::SuiteError => TestCaseResult::Error("suite error"),
+        Outcome::InfrastructureError => TestCaseResult::Error("infrastructure error"),
+    }
+}
+
+fn properties(tags: &[String], out_dir: &Path) -> String {
+    let mut properties = format!(
+        "      <property name=\"out-dir\" value=\"{}\" />\n",
+        escape(&out_dir.display().to_string())
+    );
+    if !tags.is_empty() {
+        let _ = writeln!(
+            properties,
+            "      <property name=\"tags\" value=\"{}\" />",
+            escape(&tags.join(","))
+        );
+    }
+    format!("    <properties>\n{properties}    </properties>\n")
+}
+
+/// Render the results in the junit XML format with one `<testsuite>` per
+/// SSCCE and one `<testcase>` per compiler and optimisation level.
+pub fn report<Ps: AsRef<Path>>(results: &[CompileAndRunResults<Ps>]) -> String {
+    let mut total = Counts::default();
+    let mut suites_xml = String::new();
+
+    for CompileAndRunResults {
+        suite,
+        tags,
+        sscce_out_dir,
+        errors,
+    } in results
+    {
+        let suite_name = escape(&suite.as_ref().display().to_string());
+        let mut counts = Counts::default();
+        let mut cases_xml = String::new();
+
+        let mut run_types = errors.iter().collect::<Vec<_>>();
+        run_types.sort_by_key(|((compiler, opt_level), _)| (compiler.to_string(), opt_level.id()));
+
+        for (
+            (compiler, opt_level),
+            SscceRunResult {
+                out_dir,
+                retries,
+                timings,
+                error,
+                ..
+            },
+        ) in run_types
+        {
+            counts.tests += 1;
+            let _ = write!(
+                cases_xml,
+                r#"    <testcase classname="{}" name="{} ({})" time="{:.3}""#,
+                suite_name,
+                escape(&compiler.to_string()),
+                opt_level.id(),
+                timings.total().as_secs_f64(),
+            );
+            let body = || {
+                escape(
+                    &formatting::compile_and_run_error(
+                        error.as_ref().unwrap(),
+                        suite,
+                        out_dir,
+                        *retries,
+                    )
+                    .to_string(),
+                )
+            };
+            let _ = match test_case_result(Outcome::of(error.as_ref())) {
+                TestCaseResult::Passed => writeln!(cases_xml, " />"),
+                TestCaseResult::Skipped(message) => {
+                    counts.skipped += 1;
+                    writeln!(
+                        cases_xml,
+                        ">\n      <skipped message=\"{}\">{}</skipped>\n    </testcase>",
+                        message,
+                        body()
+                    )
+                }
+                TestCaseResult::Failure(message) => {
+                    counts.failures += 1;
+                    writeln!(
+                        cases_xml,
+                        ">\n      <failure message=\"{}\">{}</failure>\n    </testcase>",
+                        message,
+                        body()
+                    )
+                }
+                TestCaseResult::Error(message) => {
+                    counts.errors += 1;
+                    writeln!(
+                        cases_xml,
+                        ">\n      <error message=\"{}\">{}</error>\n    </testcase>",
+                        message,
+                        body()
+                    )
+                }
+            };
+        }
+
+        let _ = write!(
+            suites_xml,
+            "  <testsuite name=\"{}\" {}>\n{}{}  </testsuite>\n",
+            suite_name,
+            counts.attributes(),
+            properties(tags, sscce_out_dir),
+            cases_xml
+        );
+        total.tests += counts.tests;
+        total.failures += counts.failures;
+        total.errors += counts.errors;
+        total.skipped += counts.skipped;
+    }
+
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites name=\"elm-torture\" {}>\n{}</testsuites>\n",
+        total.attributes(),
+        suites_xml
+    )
+}
+
+pub fn write_report<Ps: AsRef<Path>>(
+    path: impl AsRef<Path>,
+    results: &[CompileAndRunResults<Ps>],
+) -> io::Result<()> {
+    fs::write(path, formatting::without_colors(|| report(results)))
+}
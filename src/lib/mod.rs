@@ -1,6 +1,18 @@
+pub mod annotations;
+pub mod baseline;
 pub mod cli;
+pub mod compile_cache;
 pub mod config;
+pub mod config_layers;
+pub mod diff;
 pub mod find_suites;
+pub mod fingerprint;
 pub mod formatting;
+pub mod incremental;
+pub mod json_report;
+pub mod junit;
+pub mod progress;
 pub mod server_pool;
+pub mod shard;
 pub mod suite;
+pub mod tags;
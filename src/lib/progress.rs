@@ -0,0 +1,186 @@
+use super::config::OptimizationLevel;
+use super::suite::ElmCompilerPath;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write as _};
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// How often the progress line is redrawn when stdout is a terminal.
+pub const TTY_INTERVAL: Duration = Duration::from_millis(200);
+/// How often a progress line is printed when stdout is not a terminal.
+pub const PLAIN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Progress lines are truncated so that they do not wrap (which would break
+/// redrawing them in place).
+const MAX_LINE_WIDTH: usize = 79;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Preparing,
+    Compiling,
+    Running,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Phase::Preparing => "preparing",
+                Phase::Compiling => "compiling",
+                Phase::Running => "running",
+            }
+        )
+    }
+}
+
+type RunningKey = (String, String, OptimizationLevel);
+
+struct Display {
+    /// Whether a progress line is currently drawn on the terminal.
+    line_drawn: bool,
+    last_plain_line: Instant,
+}
+
+/// Live progress of a run, updated by the rayon workers and drawn by
+/// `tick`.
+pub struct Progress {
+    enabled: bool,
+    tty: bool,
+    total: usize,
+    start: Instant,
+    completed: AtomicUsize,
+    failed: AtomicUsize,
+    running: Mutex<BTreeMap<RunningKey, Phase>>,
+    display: Mutex<Display>,
+}
+
+fn running_key(
+    suite: &Path,
+    compiler: &ElmCompilerPath,
+    opt_level: OptimizationLevel,
+) -> RunningKey {
+    (suite.display().to_string(), compiler.to_string(), opt_level)
+}
+
+impl Progress {
+    pub fn new(total: usize, enabled: bool) -> Self {
+        let now = Instant::now();
+        Self {
+            enabled,
+            tty: io::stdout().is_terminal(),
+            total,
+            start: now,
+            completed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            running: Mutex::new(BTreeMap::new()),
+            display: Mutex::new(Display {
+                line_drawn: false,
+                last_plain_line: now,
+            }),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        if self.tty {
+            TTY_INTERVAL
+        } else {
+            PLAIN_INTERVAL
+        }
+    }
+
+    pub fn phase(
+        &self,
+        suite: &Path,
+        compiler: &ElmCompilerPath,
+        opt_level: OptimizationLevel,
+        phase: Phase,
+    ) {
+        self.running
+            .lock()
+            .unwrap()
+            .insert(running_key(suite, compiler, opt_level), phase);
+    }
+
+    pub fn run_finished(
+        &self,
+        suite: &Path,
+        compiler: &ElmCompilerPath,
+        opt_level: OptimizationLevel,
+    ) {
+        self.running
+            .lock()
+            .unwrap()
+            .remove(&running_key(suite, compiler, opt_level));
+    }
+
+    pub fn suite_finished(&self, failed: bool) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn line(&self) -> String {
+        let elapsed = self.start.elapsed().as_secs();
+        let mut line = format!(
+            "[{}/{} SSCCEs, {} failed, {:02}:{:02}]",
+            self.completed.load(Ordering::Relaxed),
+            self.total,
+            self.failed.load(Ordering::Relaxed),
+            elapsed / 60,
+            elapsed % 60,
+        );
+        let running = self.running.lock().unwrap();
+        if let Some(((suite, compiler, opt_level), phase)) = running.iter().next() {
+            let _ = write!(
+                line,
+                " {} {} ({}, {})",
+                phase,
+                suite,
+                compiler,
+                opt_level.id()
+            );
+            if running.len() > 1 {
+                let _ = write!(line, " +{} more", running.len() - 1);
+            }
+        }
+        line
+    }
+
+    /// Draw the progress line. On a terminal the line is redrawn in place,
+    /// otherwise a plain line is printed every `PLAIN_INTERVAL`.
+    pub fn tick(&self) {
+        if !self.enabled {
+            return;
+        }
+        let mut display = self.display.lock().unwrap();
+        let mut stdout = io::stdout().lock();
+        if self.tty {
+            let line = self.line().chars().take(MAX_LINE_WIDTH).collect::<String>();
+            let _ = write!(stdout, "\r\x1b[2K{line}");
+            display.line_drawn = true;
+        } else if display.last_plain_line.elapsed() >= PLAIN_INTERVAL {
+            let _ = writeln!(stdout, "{}", self.line());
+            display.last_plain_line = Instant::now();
+        }
+        let _ = stdout.flush();
+    }
+
+    /// Remove the progress line (if drawn) whilst `f` prints to stdout. The
+    /// line is redrawn on the next `tick`.
+    pub fn suspend<T>(&self, f: impl FnOnce() -> T) -> T {
+        let mut display = self.display.lock().unwrap();
+        if display.line_drawn {
+            let mut stdout = io::stdout().lock();
+            let _ = write!(stdout, "\r\x1b[2K");
+            let _ = stdout.flush();
+            display.line_drawn = false;
+        }
+        f()
+    }
+}
@@ -0,0 +1,210 @@
+use super::json_report::SuiteDurations;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// One of `count` disjoint slices of the suites, `index` counts from one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    index: usize,
+    count: usize,
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid shard {s:?}, expected <i>/<n> with 1 <= i <= n");
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index = index.trim().parse().map_err(|_| invalid())?;
+        let count = count.trim().parse().map_err(|_| invalid())?;
+        if index == 0 || index > count {
+            return Err(invalid());
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl Shard {
+    /// The suites belonging to this shard. `suites` must be in the same
+    /// (sorted) order on every machine.
+    ///
+    /// Without durations suites are dealt out in turn. With durations the
+    /// longest suites are placed first, each on the shard with the least work
+    /// so far. Suites missing from `durations` are assumed to take the mean
+    /// duration of the others.
+    pub fn select(&self, suites: &[PathBuf], durations: Option<&SuiteDurations>) -> Box<[PathBuf]> {
+        let this_shard = self.index - 1;
+        let Some(durations) = durations else {
+            return suites
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| i % self.count == this_shard)
+                .map(|(_, suite)| suite.clone())
+                .collect();
+        };
+
+        let known = suites
+            .iter()
+            .filter_map(|suite| durations.get(suite))
+            .collect::<Vec<_>>();
+        let fallback = if known.is_empty() {
+            Duration::from_secs(1)
+        } else {
+            known.iter().sum::<Duration>() / u32::try_from(known.len()).unwrap_or(u32::MAX)
+        };
+
+        let mut by_duration = suites
+            .iter()
+            .enumerate()
+            .map(|(i, suite)| (durations.get(suite).unwrap_or(fallback), i))
+            .collect::<Vec<_>>();
+        // Longest first, ties broken by position so every machine agrees.
+        by_duration.sort_by(|(d1, i1), (d2, i2)| d2.cmp(d1).then(i1.cmp(i2)));
+
+        // Each suite goes to an empty shard before any shard gets a second
+        // suite, so shards past the number of suites are always empty.
+        if this_shard >= suites.len() {
+            return Box::new([]);
+        }
+        let mut loads = vec![Duration::default(); self.count.min(suites.len())];
+        let mut mine = Vec::new();
+        for (duration, i) in by_duration {
+            let (shard, load) = loads
+                .iter_mut()
+                .enumerate()
+                .min_by_key(|(shard, load)| (**load, *shard))
+                .unwrap();
+            *load += duration;
+            if shard == this_shard {
+                mine.push(i);
+            }
+        }
+        mine.sort_unstable();
+        mine.into_iter().map(|i| suites[i].clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Shard;
+    use crate::lib::json_report::SuiteDurations;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn suites(count: usize) -> Vec<PathBuf> {
+        (0..count)
+            .map(|i| PathBuf::from(format!("s{i:02}")))
+            .collect()
+    }
+
+    fn all_shards(count: usize) -> impl Iterator<Item = Shard> {
+        (1..=count).map(move |index| Shard { index, count })
+    }
+
+    /// Every suite lands on exactly one shard and each shard keeps the input
+    /// order.
+    fn assert_partition(suites: &[PathBuf], count: usize, durations: Option<&SuiteDurations>) {
+        let mut seen = Vec::new();
+        for shard in all_shards(count) {
+            let selected = shard.select(suites, durations);
+            assert!(
+                selected.windows(2).all(|w| w[0] < w[1]),
+                "{} is not sorted: {:?}",
+                shard,
+                selected
+            );
+            seen.extend(selected.iter().cloned());
+        }
+        seen.sort();
+        assert_eq!(seen, suites);
+    }
+
+    #[test]
+    fn parses_index_and_count() {
+        assert_eq!("2/3".parse(), Ok(Shard { index: 2, count: 3 }));
+        assert_eq!(" 1 / 1 ".parse(), Ok(Shard { index: 1, count: 1 }));
+        assert_eq!(Shard { index: 2, count: 3 }.to_string(), "2/3");
+        for invalid in &["", "1", "0/3", "4/3", "1/0", "a/3", "1/b", "-1/3", "1/2/3"] {
+            assert!(
+                invalid.parse::<Shard>().is_err(),
+                "{:?} should not parse",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn round_robin_partitions_suites() {
+        let suites = suites(10);
+        for count in 1..=12 {
+            assert_partition(&suites, count, None);
+        }
+        assert_eq!(
+            &*Shard { index: 2, count: 3 }.select(&suites, None),
+            &[suites[1].clone(), suites[4].clone(), suites[7].clone()]
+        );
+    }
+
+    #[test]
+    fn balanced_partitions_suites() {
+        let suites = suites(10);
+        // Only some suites have timings, the rest fall back to the mean.
+        let durations = suites
+            .iter()
+            .step_by(2)
+            .enumerate()
+            .map(|(i, suite)| (suite.clone(), Duration::from_secs(1 << i)))
+            .collect::<SuiteDurations>();
+        for count in 1..=12 {
+            assert_partition(&suites, count, Some(&durations));
+        }
+        assert_partition(&suites, 3, Some(&SuiteDurations::default()));
+    }
+
+    #[test]
+    fn balanced_handles_huge_shard_counts() {
+        let suites = suites(3);
+        let durations = SuiteDurations::default();
+        let count = usize::MAX;
+        assert_eq!(
+            &*Shard { index: 2, count }.select(&suites, Some(&durations)),
+            &suites[1..2]
+        );
+        assert!(Shard {
+            index: count,
+            count
+        }
+        .select(&suites, Some(&durations))
+        .is_empty());
+    }
+
+    #[test]
+    fn balanced_puts_long_suites_apart() {
+        let suites = suites(4);
+        let durations = vec![
+            (suites[0].clone(), Duration::from_secs(10)),
+            (suites[1].clone(), Duration::from_secs(9)),
+            (suites[2].clone(), Duration::from_secs(1)),
+            (suites[3].clone(), Duration::from_secs(1)),
+        ]
+        .into_iter()
+        .collect::<SuiteDurations>();
+        assert_eq!(
+            &*Shard { index: 1, count: 2 }.select(&suites, Some(&durations)),
+            &[suites[0].clone(), suites[3].clone()]
+        );
+        assert_eq!(
+            &*Shard { index: 2, count: 2 }.select(&suites, Some(&durations)),
+            &suites[1..3]
+        );
+    }
+}
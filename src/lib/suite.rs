@@ -1,4 +1,9 @@
+use super::compile_cache::{self, CompileCache};
 use super::config;
+use super::find_suites;
+use super::fingerprint::CompilerHashes;
+use super::incremental::Passes;
+use super::progress::{Phase, Progress};
 use super::server_pool::Protocol;
 use super::server_pool::ServerId;
 use super::server_pool::ServerPool;
@@ -22,15 +27,23 @@ use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
 use std::process::{Output, Stdio};
 use std::sync::Arc;
-use std::{collections::HashMap, fs::File};
-use std::{fs, sync::Mutex};
+use std::thread;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::File,
+};
+use std::{
+    fs,
+    sync::{Condvar, Mutex},
+};
 use std::{io, process::Command};
 use std::{
+    path::Component,
     path::Path,
     path::PathBuf,
     string,
     sync::atomic::{AtomicBool, Ordering},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use wait_timeout::ChildExt;
 use warp::Filter;
@@ -49,6 +62,43 @@ impl<T> AnyOneOfExt for AnyOneOf<T> {
     }
 }
 
+fn read_to_buf(mut read: impl io::Read) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+
+    // read the whole file
+    read.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+/// Run `command` for at most `timeout`, killing it if it takes longer. The
+/// bool is true if the command was killed.
+///
+/// Stdout and stderr are read on threads whilst waiting: a process writing
+/// more than fits in a pipe would otherwise block until it is killed.
+fn output_with_timeout(command: &mut Command, timeout: Duration) -> io::Result<(Output, bool)> {
+    let mut child = command
+        .stdout(Stdio::piped())
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().unwrap();
+    let stderr = child.stderr.take().unwrap();
+    let stdout = thread::spawn(move || read_to_buf(stdout));
+    let stderr = thread::spawn(move || read_to_buf(stderr));
+    let (status, timed_out) = if let Some(status) = child.wait_timeout(timeout)? {
+        (status, false)
+    } else {
+        child.kill()?;
+        (child.wait()?, true)
+    };
+    let output = Output {
+        status,
+        stdout: stdout.join().expect("Reading stdout panicked")?,
+        stderr: stderr.join().expect("Reading stderr panicked")?,
+    };
+    Ok((output, timed_out))
+}
+
 fn run_until_success<T, E>(
     max_retries: usize,
     mut f: impl FnMut() -> Result<T, E>,
@@ -61,42 +111,65 @@ fn run_until_success<T, E>(
     (max_retries, f())
 }
 
-fn iter_pairs<T: Clone + Sync + Send, U: Send>(
-    into1: impl IntoParallelIterator<Item = T, Iter = impl ParallelIterator<Item = T>>,
-    into2: impl IntoParallelIterator<Item = U, Iter = impl ParallelIterator<Item = U> + Clone + Sync>,
-) -> impl IntoParallelIterator<Item = (T, U)> {
-    let it2 = into2.into_par_iter();
-    into1
-        .into_par_iter()
-        .flat_map(move |v1| it2.clone().map(move |v2| (v1.clone(), v2)))
-}
-
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub struct ElmCompilerPath {
-    unresolved: String,
+    name: String,
+    /// The binary as given in the config, before it was looked up on the
+    /// PATH.
+    binary: String,
     path: PathBuf,
+    args: Vec<String>,
+    env: BTreeMap<String, String>,
+    elm_home: Option<PathBuf>,
     pub stdlib_variant: StdlibVariant,
 }
 
 impl fmt::Display for ElmCompilerPath {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.unresolved)
+        write!(f, "{}", self.name)
     }
 }
 
 impl ElmCompilerPath {
-    fn new_resolved(binary_name: String) -> Result<Self, DetectStdlibError> {
+    fn new_resolved(compiler: &config::ElmCompiler) -> Result<Self, DetectStdlibError> {
         use bstr::ByteSlice;
-        let path = which::which(&binary_name).map_err(DetectStdlibError::LocatingCompiler)?;
-        let mut command = Command::new(&path);
+        let config::CompilerDefinition {
+            path,
+            args,
+            env,
+            elm_home,
+            stdlib_variant,
+            ..
+        } = compiler.definition();
+        let resolved_path = which::which(&path).map_err(DetectStdlibError::LocatingCompiler)?;
+        let mut resolved = Self {
+            name: compiler.name().to_string(),
+            binary: path,
+            path: resolved_path,
+            args,
+            env,
+            elm_home,
+            stdlib_variant: StdlibVariant::Official,
+        };
+        if let Some(stdlib_variant) = stdlib_variant {
+            resolved.stdlib_variant = stdlib_variant;
+            return Ok(resolved);
+        }
+
+        let mut command = resolved.command();
         command.arg("--stdlib-variant");
-        set_elm_home(&mut command);
+        match &resolved.elm_home {
+            Some(elm_home) => {
+                command.env("ELM_HOME", elm_home);
+            }
+            None => set_elm_home(&mut command),
+        }
 
         debug!("Invoking compiler to detect stdlib variant: {:?}", command);
 
         let Output { status, stdout, .. } = command.output().map_err(DetectStdlibError::Io)?;
 
-        let stdlib_variant = if status.success() {
+        resolved.stdlib_variant = if status.success() {
             if stdout.trim().starts_with(b"another-elm") {
                 Ok(StdlibVariant::Another)
             } else {
@@ -105,15 +178,38 @@ impl ElmCompilerPath {
         } else {
             Ok(StdlibVariant::Official)
         }?;
-        Ok(Self {
-            unresolved: binary_name,
-            path,
-            stdlib_variant,
-        })
+        Ok(resolved)
+    }
+
+    /// The name used for the compiler in reports.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn binary(&self) -> &str {
+        &self.binary
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn env(&self) -> &BTreeMap<String, String> {
+        &self.env
+    }
+
+    pub fn elm_home(&self) -> Option<&Path> {
+        self.elm_home.as_deref()
     }
 
     fn command(&self) -> Command {
-        Command::new(&self.path)
+        Command::new(&self.path).also(|command| {
+            command.envs(&self.env);
+        })
     }
 }
 
@@ -273,6 +369,64 @@ pub struct Config<Readiness> {
     run_fails_if: Option<ConditionCollection<RunFailsIfAll>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     skip_run_if: Option<ConditionCollection<RunFailsIfAll>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tags: Option<Box<[String]>>,
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    run_timeout: Option<Duration>,
+    #[serde(
+        default,
+        with = "humantime_serde",
+        skip_serializing_if = "Option::is_none"
+    )]
+    compile_timeout: Option<Duration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compiler_max_retries: Option<usize>,
+}
+
+/// A limit on compiling or running a suite. The suite's output.json can
+/// override the limit set in the elm-torture config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit<T> {
+    pub value: T,
+    pub set_by_suite: bool,
+}
+
+impl<T> Limit<T> {
+    fn new(from_suite: Option<T>, from_config: T) -> Self {
+        match from_suite {
+            Some(value) => Self {
+                value,
+                set_by_suite: true,
+            },
+            None => Self {
+                value: from_config,
+                set_by_suite: false,
+            },
+        }
+    }
+}
+
+impl<Readiness> Config<Readiness> {
+    fn run_timeout(&self, config: &config::Config) -> Limit<Duration> {
+        Limit::new(self.run_timeout, config.run_timeout())
+    }
+
+    fn compile_limits(&self, config: &config::Config) -> CompileLimits {
+        CompileLimits {
+            timeout: Limit::new(self.compile_timeout.map(Some), config.compile_timeout()),
+            max_retries: Limit::new(self.compiler_max_retries, config.compiler_max_retries()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct CompileLimits {
+    timeout: Limit<Option<Duration>>,
+    max_retries: Limit<usize>,
 }
 
 impl Config<Raw> {
@@ -288,6 +442,10 @@ impl Config<Raw> {
             compile_fails_if,
             run_fails_if,
             skip_run_if,
+            tags,
+            run_timeout,
+            compile_timeout,
+            compiler_max_retries,
         } = self;
 
         let mut flags = flags.map_or_else(Map::new, |Flags(flags, _)| flags);
@@ -312,18 +470,45 @@ impl Config<Raw> {
             compile_fails_if,
             run_fails_if,
             skip_run_if,
+            tags,
+            run_timeout,
+            compile_timeout,
+            compiler_max_retries,
         })
     }
 }
 
 #[derive(Debug)]
 pub enum CompileError {
+    CopyingSuite(io::Error),
+    SeedingElmHome(io::Error),
     Process(io::Error),
     Compiler(Output),
     CompilerStdErrNotEmpty(Output),
     ReadingTargets(io::Error),
     DeletingElmStuff(io::Error),
     SuiteDoesNotExist,
+    Timeout {
+        after: Limit<Duration>,
+        stdout: Vec<u8>,
+        stderr: Vec<u8>,
+    },
+}
+
+impl CompileError {
+    fn is_infrastructure(&self) -> bool {
+        match self {
+            Self::CopyingSuite(_)
+            | Self::SeedingElmHome(_)
+            | Self::Process(_)
+            | Self::DeletingElmStuff(_) => true,
+            Self::Compiler(_)
+            | Self::CompilerStdErrNotEmpty(_)
+            | Self::ReadingTargets(_)
+            | Self::SuiteDoesNotExist
+            | Self::Timeout { .. } => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -350,13 +535,113 @@ pub enum RunError {
     WritingExpectedOutput(io::Error),
     ExpectedOutputNotUtf8(string::FromUtf8Error),
     OutputProduced(Output),
+    PortMismatch {
+        port: String,
+        /// One-based index into the `ports` list of output.json.
+        event_index: usize,
+        expected: Box<serde_json::Value>,
+        actual: Box<serde_json::Value>,
+        output: Output,
+    },
+    LogsMismatch {
+        expected: String,
+        actual: String,
+        output: Output,
+    },
     Timeout {
-        after: Duration,
+        after: Limit<Duration>,
         stdout: Vec<u8>,
         stderr: Vec<u8>,
     },
 }
 
+impl RunError {
+    fn is_infrastructure(&self) -> bool {
+        match self {
+            Self::NodeNotFound(_)
+            | Self::NodeProcess(_)
+            | Self::WritingHarness(_)
+            | Self::CopyingExpectedOutput(_)
+            | Self::WritingExpectedOutput(_)
+            | Self::ExpectedOutputNotUtf8(_) => true,
+            Self::SuiteDoesNotExist
+            | Self::Runtime(_)
+            | Self::OutputProduced(_)
+            | Self::PortMismatch { .. }
+            | Self::LogsMismatch { .. }
+            | Self::Timeout { .. } => false,
+        }
+    }
+}
+
+/// The test harness that runs the compiled suite.
+pub const HARNESS_JS: &[u8] = include_bytes!("../../embed-assets/run.js");
+
+pub const XML_HTTP_REQUEST_JS: &[u8] =
+    include_bytes!("../../embed-assets/elm-serverless/src-bridge/xmlhttprequest.js");
+
+/// Structured failures that the test harness (embed-assets/run.js) writes to
+/// stderr prefixed by `HARNESS_FAILURE_MARKER`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum HarnessFailure {
+    #[serde(rename_all = "camelCase")]
+    PortMismatch {
+        port: String,
+        event_index: usize,
+        #[serde(default)]
+        expected: serde_json::Value,
+        #[serde(default)]
+        actual: serde_json::Value,
+    },
+    LogsMismatch {
+        expected: String,
+        actual: String,
+    },
+}
+
+const HARNESS_FAILURE_MARKER: &[u8] = b"elm-torture-failure: ";
+
+impl HarnessFailure {
+    fn from_stderr(stderr: &[u8]) -> Option<Self> {
+        use bstr::ByteSlice;
+        stderr
+            .lines()
+            .find_map(|line| line.strip_prefix(HARNESS_FAILURE_MARKER))
+            .and_then(|json| serde_json::from_slice(json).ok())
+    }
+
+    fn into_run_error(self, output: Output) -> RunError {
+        match self {
+            Self::PortMismatch {
+                port,
+                event_index,
+                expected,
+                actual,
+            } => RunError::PortMismatch {
+                port,
+                event_index,
+                expected: Box::new(expected),
+                actual: Box::new(actual),
+                output,
+            },
+            Self::LogsMismatch { expected, actual } => RunError::LogsMismatch {
+                expected,
+                actual,
+                output,
+            },
+        }
+    }
+}
+
+/// Problems with the machine elm-torture is running on (rather than with the
+/// suite or the compiler under test) that stopped a suite from being tested.
+#[derive(Debug)]
+pub enum InfrastructureError {
+    Compile(CompileError),
+    Run(RunError),
+}
+
 #[derive(Debug)]
 pub enum CompileAndRunError {
     SuiteNotExist,
@@ -366,6 +651,7 @@ pub enum CompileAndRunError {
     CannotGetSuiteConfig(GetSuiteConfigError),
     CompileFailure {
         allowed: bool,
+        max_retries: Limit<usize>,
         reason: super::suite::CompileError,
     },
     RunFailure {
@@ -375,6 +661,215 @@ pub enum CompileAndRunError {
     ExpectedCompileFailure,
     ExpectedRunFailure,
     Server(anyhow::Error),
+    Infrastructure(InfrastructureError),
+}
+
+impl CompileAndRunError {
+    /// The category this error is reported in (see exit-codes.md), None if
+    /// the failure is allowed. Derived from the `Outcome` so that the exit
+    /// code agrees with the reports.
+    pub fn failure_category(&self) -> Option<config::FailureCategory> {
+        use config::FailureCategory;
+        match Outcome::of(Some(self)) {
+            Outcome::Success | Outcome::AllowedCompileFailure | Outcome::AllowedRunFailure => None,
+            Outcome::CompileFailure | Outcome::RunFailure if self.is_timeout() => {
+                Some(FailureCategory::Timeout)
+            }
+            Outcome::CompileFailure => Some(FailureCategory::CompileFailure),
+            Outcome::RunFailure => Some(FailureCategory::RunFailure),
+            Outcome::ExpectedCompileFailure | Outcome::ExpectedRunFailure => {
+                Some(FailureCategory::UnexpectedSuccess)
+            }
+            Outcome::SuiteError => Some(FailureCategory::Other),
+            Outcome::InfrastructureError => Some(FailureCategory::Infrastructure),
+        }
+    }
+
+    fn is_timeout(&self) -> bool {
+        matches!(
+            self,
+            Self::CompileFailure {
+                reason: CompileError::Timeout { .. },
+                ..
+            } | Self::RunFailure {
+                reason: RunError::Timeout { .. },
+                ..
+            }
+        )
+    }
+}
+
+/// Coarse classification of the result of compiling and running a suite
+/// with one compiler at one optimisation level.
+#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, Clone, Copy, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Success,
+    AllowedCompileFailure,
+    AllowedRunFailure,
+    CompileFailure,
+    RunFailure,
+    ExpectedCompileFailure,
+    ExpectedRunFailure,
+    /// The suite itself is broken, for example its output.json is invalid.
+    SuiteError,
+    InfrastructureError,
+}
+
+impl Outcome {
+    #[allow(clippy::enum_glob_use)]
+    pub fn of(error: Option<&CompileAndRunError>) -> Self {
+        use CompileAndRunError::*;
+        match error {
+            None => Self::Success,
+            Some(CompileFailure { allowed: true, .. }) => Self::AllowedCompileFailure,
+            Some(RunFailure { allowed: true, .. }) => Self::AllowedRunFailure,
+            Some(CompileFailure { .. }) => Self::CompileFailure,
+            Some(RunFailure { .. }) => Self::RunFailure,
+            Some(ExpectedCompileFailure) => Self::ExpectedCompileFailure,
+            Some(ExpectedRunFailure) => Self::ExpectedRunFailure,
+            Some(
+                SuiteNotExist | SuiteNotDir | SuiteNotElm | CannotGetSuiteConfig(_) | Server(_),
+            ) => Self::SuiteError,
+            Some(OutDirIsNotDir | Infrastructure(_)) => Self::InfrastructureError,
+        }
+    }
+
+    /// Whether elm-torture considers this outcome a failure of the suite.
+    pub fn is_failure(self) -> bool {
+        match self {
+            Self::Success | Self::AllowedCompileFailure | Self::AllowedRunFailure => false,
+            Self::CompileFailure
+            | Self::RunFailure
+            | Self::ExpectedCompileFailure
+            | Self::ExpectedRunFailure
+            | Self::SuiteError
+            | Self::InfrastructureError => true,
+        }
+    }
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Self::Success => "success",
+                Self::AllowedCompileFailure => "allowed compile failure",
+                Self::AllowedRunFailure => "allowed run failure",
+                Self::CompileFailure => "compile failure",
+                Self::RunFailure => "run failure",
+                Self::ExpectedCompileFailure =>
+                    "success when elm-torture expected a compile time failure",
+                Self::ExpectedRunFailure => "success when elm-torture expected a run time failure",
+                Self::SuiteError => "suite error",
+                Self::InfrastructureError => "infrastructure error",
+            }
+        )
+    }
+}
+
+/// The `ELM_HOME` that elm uses when the environment variable is not set.
+fn default_elm_home() -> Option<PathBuf> {
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(|app_data| Path::new(&app_data).join("elm"))
+    } else {
+        env::var_os("HOME").map(|home| Path::new(&home).join(".elm"))
+    }
+}
+
+struct CompileWorker {
+    dir: PathBuf,
+    /// Keyed by the `elm-home` they were seeded from, None for the user's.
+    elm_homes: HashMap<Option<PathBuf>, PathBuf>,
+}
+
+/// At most one compiler runs per worker at a time and each worker has its
+/// own `ELM_HOME` so that concurrent compilations do not race on elm's package
+/// cache. Each `ELM_HOME` is seeded (on first use) with a copy of the user's,
+/// or of the `elm-home` of the compiler definition if it has one (in which
+/// case the worker keeps a separate `ELM_HOME` for that compiler).
+pub struct CompileWorkers {
+    dir: tempfile::TempDir,
+    idle: Mutex<Vec<CompileWorker>>,
+    worker_returned: Condvar,
+}
+
+struct CompileWorkerGuard<'a> {
+    workers: &'a CompileWorkers,
+    worker: Option<CompileWorker>,
+}
+
+impl CompileWorkers {
+    fn new(jobs: usize) -> io::Result<Self> {
+        let dir = tempfile::Builder::new()
+            .prefix("elm-torture-elm-home")
+            .tempdir()?;
+        let idle = (0..jobs)
+            .map(|i| CompileWorker {
+                dir: dir.path().join(i.to_string()),
+                elm_homes: HashMap::new(),
+            })
+            .collect();
+        Ok(Self {
+            dir,
+            idle: Mutex::new(idle),
+            worker_returned: Condvar::new(),
+        })
+    }
+
+    fn acquire(&self) -> CompileWorkerGuard<'_> {
+        let mut idle = self.idle.lock().unwrap();
+        loop {
+            if let Some(worker) = idle.pop() {
+                return CompileWorkerGuard {
+                    workers: self,
+                    worker: Some(worker),
+                };
+            }
+            idle = self.worker_returned.wait(idle).unwrap();
+        }
+    }
+}
+
+impl CompileWorkerGuard<'_> {
+    /// The `ELM_HOME` to use for a compiler whose definition gives
+    /// `seed` as its `elm-home`.
+    fn elm_home(&mut self, seed: Option<&Path>) -> io::Result<&Path> {
+        let worker = self.worker.as_mut().unwrap();
+        let key = seed.map(Path::to_path_buf);
+        if !worker.elm_homes.contains_key(&key) {
+            let elm_home = worker.dir.join(worker.elm_homes.len().to_string());
+            debug!(
+                "Seeding ELM_HOME {} (all ELM_HOMEs are in {})",
+                elm_home.display(),
+                self.workers.dir.path().display()
+            );
+            match seed
+                .map(Path::to_path_buf)
+                .or_else(|| env::var_os("ELM_HOME").map(PathBuf::from))
+                .or_else(default_elm_home)
+            {
+                Some(seed) if seed.is_dir() => {
+                    // Copy rather than link as elm rewrites some files in place.
+                    copy_dir(&seed, &elm_home, false)?;
+                }
+                _ => fs::create_dir_all(&elm_home)?,
+            }
+            worker.elm_homes.insert(key.clone(), elm_home);
+        }
+        Ok(&worker.elm_homes[&key])
+    }
+}
+
+impl Drop for CompileWorkerGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(worker) = self.worker.take() {
+            self.workers.idle.lock().unwrap().push(worker);
+            self.workers.worker_returned.notify_one();
+        }
+    }
 }
 
 fn set_elm_home(command: &mut Command) {
@@ -383,19 +878,49 @@ fn set_elm_home(command: &mut Command) {
     }
 }
 
+/// Wait for the compiler to finish, killing it if it takes longer than
+/// `timeout`.
+fn wait_for_compiler(
+    command: &mut Command,
+    timeout: Limit<Option<Duration>>,
+) -> Result<Output, CompileError> {
+    let timeout = match timeout.value {
+        Some(value) => Limit {
+            value,
+            set_by_suite: timeout.set_by_suite,
+        },
+        None => return command.output().map_err(CompileError::Process),
+    };
+    let (output, timed_out) =
+        output_with_timeout(command, timeout.value).map_err(CompileError::Process)?;
+    if timed_out {
+        Err(CompileError::Timeout {
+            after: timeout,
+            stdout: output.stdout,
+            stderr: output.stderr,
+        })
+    } else {
+        Ok(output)
+    }
+}
+
 fn compile(
     suite: &Path,
     out_file: impl AsRef<Path>,
-    compiler_lock: &Mutex<()>,
+    workers: &CompileWorkers,
     opt_level: OptimizationLevel,
     compiler_path: &ElmCompilerPath,
-    config: &config::Config,
+    limits: CompileLimits,
+    timings: &mut Timings,
 ) -> (usize, Result<(), CompileError>) {
     fn compile_help(
         suite: impl AsRef<Path>,
         command: &mut Command,
+        timeout: Limit<Option<Duration>>,
+        timings: &mut Timings,
     ) -> Result<Output, CompileError> {
-        fs::remove_dir_all(suite.as_ref().join("elm-stuff"))
+        let start = Instant::now();
+        let deleted = fs::remove_dir_all(suite.as_ref().join("elm-stuff"))
             .or_else(|e| {
                 if e.kind() == io::ErrorKind::NotFound {
                     Ok(())
@@ -403,8 +928,14 @@ fn compile(
                     Err(e)
                 }
             })
-            .map_err(CompileError::DeletingElmStuff)?;
-        let output = command.output().map_err(CompileError::Process)?;
+            .map_err(CompileError::DeletingElmStuff);
+        timings.delete_elm_stuff += start.elapsed();
+        deleted?;
+
+        let start = Instant::now();
+        let output = wait_for_compiler(command, timeout);
+        timings.compile.push(start.elapsed());
+        let output = output?;
 
         if !output.status.success() {
             return Err(CompileError::Compiler(output));
@@ -438,15 +969,21 @@ fn compile(
     command.arg("make");
     command.args(root_files);
     command.args(opt_level.args().iter());
+    command.args(&compiler_path.args);
     command.arg("--output");
-    set_elm_home(&mut command);
     command.arg(out_file.as_ref());
 
     debug!("Invoking compiler: {:?}", command);
 
-    let (retries, _) = match run_until_success(config.compiler_max_retries(), || {
-        let _lock = compiler_lock.lock();
-        compile_help(&suite, &mut command)
+    let (retries, _) = match run_until_success(limits.max_retries.value, || {
+        let mut worker = workers.acquire();
+        command.env(
+            "ELM_HOME",
+            worker
+                .elm_home(compiler_path.elm_home.as_deref())
+                .map_err(CompileError::SeedingElmHome)?,
+        );
+        compile_help(&suite, &mut command, limits.timeout, timings)
     }) {
         (r, Ok(op)) => (r, op),
         (r, Err(e)) => return (r, Err(e)),
@@ -455,6 +992,60 @@ fn compile(
     (retries, Ok(()))
 }
 
+fn copy_dir(from: &Path, to: &Path, hard_link: bool) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_name() == "elm-stuff" {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target, hard_link)?;
+        } else if !hard_link || fs::hard_link(entry.path(), &target).is_err() {
+            fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copy `suite` (without its elm-stuff) to `scratch` so that it can be
+/// compiled without touching the original. The copied elm.json points at the
+/// original source directories, so only elm-stuff is written to the copy.
+fn copy_suite(suite: &Path, scratch: &Path) -> io::Result<()> {
+    if scratch.exists() {
+        fs::remove_dir_all(scratch)?;
+    }
+    copy_dir(suite, scratch, true)?;
+
+    let elm_json_path = scratch.join("elm.json");
+    let mut elm_json: serde_json::Value = serde_json::from_slice(&fs::read(&elm_json_path)?)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    // Relative source directories would resolve against the scratch copy, so
+    // point them all back at the original suite.
+    let suite = fs::canonicalize(suite)?;
+    if let Some(serde_json::Value::Array(dirs)) = elm_json.get_mut("source-directories") {
+        for dir in dirs {
+            if let serde_json::Value::String(dir) = dir {
+                let path = Path::new(dir.as_str());
+                if path.is_relative() {
+                    let original = suite.join(path);
+                    *dir = fs::canonicalize(&original)
+                        .unwrap_or(original)
+                        .to_string_lossy()
+                        .into_owned();
+                }
+            }
+        }
+    }
+    // elm.json may be a hard link to the original.
+    fs::remove_file(&elm_json_path)?;
+    fs::write(
+        &elm_json_path,
+        serde_json::to_vec_pretty(&elm_json).expect("Failed to reserialize elm.json"),
+    )
+}
+
 fn get_suite_config(suite: impl AsRef<Path>) -> Result<Config<Raw>, GetSuiteConfigError> {
     let expected_output_path = suite.as_ref().join("output.json");
     serde_json::from_reader(StripComments::new(
@@ -465,6 +1056,11 @@ fn get_suite_config(suite: impl AsRef<Path>) -> Result<Config<Raw>, GetSuiteConf
     .map_err(GetSuiteConfigError::Parse)
 }
 
+/// The tags listed in the output.json of `suite`.
+pub fn suite_tags(suite: impl AsRef<Path>) -> Result<Box<[String]>, GetSuiteConfigError> {
+    Ok(get_suite_config(suite)?.tags.unwrap_or_default())
+}
+
 #[allow(clippy::too_many_lines)]
 fn run(
     suite: &Path,
@@ -473,14 +1069,6 @@ fn run(
     config: &config::Config,
     suite_config: &Config<Ready>,
 ) -> Result<(), RunError> {
-    fn read_to_buf(mut read: impl io::Read) -> io::Result<Vec<u8>> {
-        let mut buffer = Vec::new();
-
-        // read the whole file
-        read.read_to_end(&mut buffer)?;
-        Ok(buffer)
-    }
-
     if !suite.join("elm.json").exists() {
         return Err(RunError::SuiteDoesNotExist);
     }
@@ -490,16 +1078,8 @@ fn run(
     let output_file = out_dir.join("output.json");
     let main_file = out_dir.join("main.js");
 
-    fs::write(
-        &harness_file,
-        &include_bytes!("../../embed-assets/run.js")[..],
-    )
-    .map_err(RunError::WritingHarness)?;
-    fs::write(
-        &xml_http_request_file,
-        &include_bytes!("../../embed-assets/elm-serverless/src-bridge/xmlhttprequest.js")[..],
-    )
-    .map_err(RunError::WritingHarness)?;
+    fs::write(&harness_file, HARNESS_JS).map_err(RunError::WritingHarness)?;
+    fs::write(&xml_http_request_file, XML_HTTP_REQUEST_JS).map_err(RunError::WritingHarness)?;
     fs::write(
         &output_file,
         &serde_json::to_vec_pretty(&suite_config).expect("Failed to reserialize output json"),
@@ -527,46 +1107,27 @@ harness(generated, expectedOutput);
     // We pick a timezone **without** changes in offset for consistent testing.
     let tz = "Asia/Bahrain";
 
-    let mut runner_child = Command::new(node_exe)
-        .arg("--unhandled-rejections=strict")
-        .arg(&main_file)
-        .env("TZ", tz)
-        .stdout(Stdio::piped())
-        .stdin(Stdio::null())
-        .stderr(Stdio::piped())
-        .spawn()
-        .map_err(RunError::NodeProcess)?;
-
-    let runner_status = runner_child
-        .wait_timeout(config.run_timeout())
-        .map_err(RunError::NodeProcess)?
-        .map_or_else(
-            || {
-                runner_child.kill().map_err(RunError::NodeProcess)?;
-                let stdout = read_to_buf(runner_child.stdout.as_mut().unwrap())
-                    .map_err(RunError::NodeProcess)?;
-                let stderr = read_to_buf(runner_child.stderr.as_mut().unwrap())
-                    .map_err(RunError::NodeProcess)?;
-                Err(RunError::Timeout {
-                    after: config.run_timeout(),
-                    stdout,
-                    stderr,
-                })
-            },
-            Ok,
-        )?;
-
-    let stdout = read_to_buf(runner_child.stdout.unwrap()).map_err(RunError::NodeProcess)?;
-    let stderr = read_to_buf(runner_child.stderr.unwrap()).map_err(RunError::NodeProcess)?;
-
-    let output = Output {
-        status: runner_status,
-        stdout,
-        stderr,
-    };
+    let (output, timed_out) = output_with_timeout(
+        Command::new(node_exe)
+            .arg("--unhandled-rejections=strict")
+            .arg(&main_file)
+            .env("TZ", tz),
+        suite_config.run_timeout(config).value,
+    )
+    .map_err(RunError::NodeProcess)?;
+    if timed_out {
+        return Err(RunError::Timeout {
+            after: suite_config.run_timeout(config),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        });
+    }
 
     if !output.status.success() {
-        return Err(RunError::Runtime(output));
+        return Err(match HarnessFailure::from_stderr(&output.stderr) {
+            Some(failure) => failure.into_run_error(output),
+            None => RunError::Runtime(output),
+        });
     }
     if !output.stdout.is_empty() {
         return Err(RunError::OutputProduced(output));
@@ -589,14 +1150,55 @@ harness(generated, expectedOutput);
 
 pub type SscceRunType = (ElmCompilerPath, OptimizationLevel);
 
+/// Wall-clock time spent in each phase of testing a suite with one compiler
+/// at one optimisation level.
+#[derive(Debug, Default, Clone)]
+pub struct Timings {
+    /// Summed over every compilation attempt.
+    pub delete_elm_stuff: Duration,
+    /// One entry per compilation attempt (including retries).
+    pub compile: Vec<Duration>,
+    /// None if the SSCCE was not run.
+    pub run: Option<Duration>,
+}
+
+impl Timings {
+    pub fn total_compile(&self) -> Duration {
+        self.compile.iter().sum()
+    }
+
+    pub fn total(&self) -> Duration {
+        self.delete_elm_stuff + self.total_compile() + self.run.unwrap_or_default()
+    }
+}
+
+#[derive(Debug)]
+pub struct SscceRunResult {
+    /// Where the built files for this compiler and optimisation level go.
+    pub out_dir: PathBuf,
+    pub retries: usize,
+    pub timings: Timings,
+    /// The suite compiled but was not run because of `skip-run-if`.
+    pub run_skipped: bool,
+    /// None if the compile cache was not used.
+    pub cache_lookup: Option<compile_cache::Lookup>,
+    /// The suite was not compiled or run because it passed with the same
+    /// inputs in a previous run (see `--incremental`).
+    pub cached_pass: bool,
+    /// None indicates that elm-torture ran SSCCE successfully.
+    pub error: Option<CompileAndRunError>,
+}
+
 #[allow(clippy::too_many_lines)]
 fn compile_and_run(
     suite: impl AsRef<Path> + Sync,
     out_dir: impl AsRef<Path> + Sync,
-    compiler_lock: &Mutex<()>,
+    workers: &CompileWorkers,
+    cache: Option<&CompileCache>,
     configurations: impl IntoParallelIterator<Item = SscceRunType>,
     config: &config::Config,
-) -> HashMap<SscceRunType, (usize, Result<(), CompileAndRunError>)> {
+    progress: &Progress,
+) -> HashMap<SscceRunType, SscceRunResult> {
     let platform = match env::consts::OS {
         "linux" => Platform::Linux,
         "macos" => Platform::MacOs,
@@ -604,10 +1206,18 @@ fn compile_and_run(
         _ => panic!("Unsupported platform. (Add it to Platform enum!)"),
     };
     let server_pool = ServerPool::new().unwrap();
+    let suite_lock = Mutex::new(());
     configurations
         .into_par_iter()
         .map(|(elm_compiler, opt_level)| {
-            let res: (_, _) = crossbeam::scope(|_| {
+            let mut timings = Timings::default();
+            let mut run_skipped = false;
+            let mut cache_lookup = None;
+            let out_dir = out_dir
+                .as_ref()
+                .join(run_out_dir_name(&elm_compiler, opt_level));
+            progress.phase(suite.as_ref(), &elm_compiler, opt_level, Phase::Preparing);
+            let (retries, res) = crossbeam::scope(|_| {
                 if !suite.as_ref().exists() {
                     return (0, Err(CompileAndRunError::SuiteNotExist));
                 }
@@ -617,6 +1227,9 @@ fn compile_and_run(
                 if !suite.as_ref().join("elm.json").exists() {
                     return (0, Err(CompileAndRunError::SuiteNotElm));
                 }
+                if fs::create_dir_all(&out_dir).is_err() || !out_dir.is_dir() {
+                    return (0, Err(CompileAndRunError::OutDirIsNotDir));
+                }
 
                 let suite_config = match get_suite_config(&suite)
                     .map_err(CompileAndRunError::CannotGetSuiteConfig)
@@ -662,24 +1275,85 @@ fn compile_and_run(
                             platform,
                         });
 
-                let retries = match compile(
-                    suite.as_ref(),
-                    out_dir.as_ref().join(format!("elm-{}.js", opt_level.id())),
-                    &compiler_lock,
-                    opt_level,
-                    &elm_compiler,
-                    &config,
-                ) {
-                    (r, Ok(())) => (r),
-                    (r, Err(e)) => {
-                        debug!("Compiler failure compiling {}", suite.as_ref().display());
-                        return (
-                            r,
-                            Err(CompileAndRunError::CompileFailure {
-                                allowed: compile_failure_allowed,
-                                reason: e,
-                            }),
-                        );
+                let compile_limits = suite_config.compile_limits(config);
+                let out_file = out_dir.join(format!("elm-{}.js", opt_level.id()));
+                let cache_key = cache.and_then(|cache| {
+                    match cache.key(suite.as_ref(), &elm_compiler, opt_level) {
+                        Ok(key) => Some((cache, key)),
+                        Err(e) => {
+                            debug!(
+                                "Not caching {} as it could not be hashed: {}",
+                                suite.as_ref().display(),
+                                e
+                            );
+                            None
+                        }
+                    }
+                });
+                cache_lookup = cache_key
+                    .as_ref()
+                    .map(|(cache, key)| cache.restore(key, &out_file));
+
+                let retries = if cache_lookup == Some(compile_cache::Lookup::Hit) {
+                    0
+                } else {
+                    let build_dir = if config.scratch_copy() {
+                        let scratch = out_dir.join("suite");
+                        if let Err(e) = copy_suite(suite.as_ref(), &scratch) {
+                            return (
+                                0,
+                                Err(CompileAndRunError::Infrastructure(
+                                    InfrastructureError::Compile(CompileError::CopyingSuite(e)),
+                                )),
+                            );
+                        }
+                        scratch
+                    } else {
+                        suite.as_ref().to_path_buf()
+                    };
+
+                    progress.phase(suite.as_ref(), &elm_compiler, opt_level, Phase::Compiling);
+                    // Builds in the suite directory share its elm-stuff.
+                    // Scratch copies have their own.
+                    let suite_guard = (!config.scratch_copy()).then(|| suite_lock.lock());
+                    let compile_result = compile(
+                        &build_dir,
+                        &out_file,
+                        workers,
+                        opt_level,
+                        &elm_compiler,
+                        compile_limits,
+                        &mut timings,
+                    );
+                    drop(suite_guard);
+                    match compile_result {
+                        (r, Ok(())) => {
+                            if let Some((cache, key)) = &cache_key {
+                                if let Err(e) = cache.store(key, &out_file) {
+                                    debug!("Could not write to the compile cache: {e}");
+                                }
+                            }
+                            r
+                        }
+                        (r, Err(e)) if e.is_infrastructure() => {
+                            return (
+                                r,
+                                Err(CompileAndRunError::Infrastructure(
+                                    InfrastructureError::Compile(e),
+                                )),
+                            );
+                        }
+                        (r, Err(e)) => {
+                            debug!("Compiler failure compiling {}", suite.as_ref().display());
+                            return (
+                                r,
+                                Err(CompileAndRunError::CompileFailure {
+                                    allowed: compile_failure_allowed,
+                                    max_retries: compile_limits.max_retries,
+                                    reason: e,
+                                }),
+                            );
+                        }
                     }
                 };
 
@@ -692,6 +1366,7 @@ fn compile_and_run(
                     stdlib_variant: elm_compiler.stdlib_variant,
                     platform,
                 }) {
+                    run_skipped = true;
                     return (retries, Ok(()));
                 }
 
@@ -706,21 +1381,30 @@ fn compile_and_run(
                     run_failure_required, &suite_config.run_fails_if, elm_compiler.stdlib_variant
                 );
 
-                if let Err(e) = run(
-                    suite.as_ref(),
-                    out_dir.as_ref(),
-                    opt_level,
-                    &config,
-                    &suite_config,
-                ) {
-                    return (
-                        retries,
-                        Err(CompileAndRunError::RunFailure {
-                            allowed: run_failure_required,
-                            reason: e,
-                        }),
-                    );
-                };
+                progress.phase(suite.as_ref(), &elm_compiler, opt_level, Phase::Running);
+                let start = Instant::now();
+                let run_result = run(suite.as_ref(), &out_dir, opt_level, &config, &suite_config);
+                timings.run = Some(start.elapsed());
+                match run_result {
+                    Err(e) if e.is_infrastructure() => {
+                        return (
+                            retries,
+                            Err(CompileAndRunError::Infrastructure(
+                                InfrastructureError::Run(e),
+                            )),
+                        );
+                    }
+                    Err(e) => {
+                        return (
+                            retries,
+                            Err(CompileAndRunError::RunFailure {
+                                allowed: run_failure_required,
+                                reason: e,
+                            }),
+                        );
+                    }
+                    Ok(()) => {}
+                }
 
                 if run_failure_required {
                     return (retries, Err(CompileAndRunError::ExpectedRunFailure));
@@ -728,30 +1412,126 @@ fn compile_and_run(
                 (retries, Ok(()))
             })
             .unwrap();
-            ((elm_compiler, opt_level), res)
+            progress.run_finished(suite.as_ref(), &elm_compiler, opt_level);
+            (
+                (elm_compiler, opt_level),
+                SscceRunResult {
+                    out_dir,
+                    retries,
+                    timings,
+                    run_skipped,
+                    cache_lookup,
+                    cached_pass: false,
+                    error: res.err(),
+                },
+            )
         })
         .collect()
 }
 
 pub struct CompileAndRunResults<Ps> {
     pub suite: Ps,
+    pub tags: Box<[String]>,
     // TODO(harry): move into RunError!
     pub sscce_out_dir: PathBuf,
-    /// None indicates that elm-torture ran SSCCE successfully. FIrst element
-    /// in tuple is retry count.
-    pub errors: HashMap<SscceRunType, (usize, Option<CompileAndRunError>)>,
+    pub errors: HashMap<SscceRunType, SscceRunResult>,
+}
+
+/// Where the built files for one compiler and optimisation level go, relative
+/// to the out dir of the suite.
+fn run_out_dir_name(compiler: &ElmCompilerPath, opt_level: OptimizationLevel) -> PathBuf {
+    [compiler_dir_name(compiler).as_str(), opt_level.id()]
+        .iter()
+        .collect()
+}
+
+/// A single path component for `compiler`: never `.` or `..` and without
+/// path separators.
+fn compiler_dir_name(compiler: &ElmCompilerPath) -> String {
+    let name = compiler.name();
+    let only_dots = name.chars().all(|c| c == '.');
+    name.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || (c == '.' && !only_dots) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Where the built files of `suite` go, relative to the out dir. This mirrors
+/// the suites directory so that suites with the same name in different
+/// directories do not collide.
+fn sscce_out_dir_name(suite: &Path, suites_dir: &Path) -> PathBuf {
+    let relative = find_suites::relative_path(suite, suites_dir)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect::<PathBuf>();
+    if relative.as_os_str().is_empty() {
+        // `suites_dir` is itself a suite.
+        suite
+            .file_name()
+            .map_or_else(|| PathBuf::from("suite"), PathBuf::from)
+    } else {
+        relative
+    }
+}
+
+/// Remove `dir` and then each of its ancestors below `root`, stopping at the
+/// first one that is not empty.
+fn remove_empty_dirs(dir: &Path, root: &Path) {
+    for dir in dir.ancestors().take_while(|dir| *dir != root) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+    }
 }
 
 pub enum SuitesError {
-    ResolvingCompiler(DetectStdlibError),
+    ResolvingCompiler {
+        compiler: String,
+        error: DetectStdlibError,
+    },
+    CreatingElmHomes(io::Error),
+    /// Two compilers would put their built files in the same directory.
+    CompilerDirClash {
+        first: String,
+        second: String,
+        dir: String,
+    },
     // CompilerNotFound(which::Error),
     // CannotDetectStdlibVariant(DetectStdlibError),
 }
 
+impl SuitesError {
+    /// The category this error is reported in (see exit-codes.md). A compiler
+    /// that cannot be found or run, or a temporary directory that cannot be
+    /// created, is a problem with the machine rather than the compiler.
+    pub fn failure_category(&self) -> config::FailureCategory {
+        use config::FailureCategory;
+        match self {
+            Self::ResolvingCompiler {
+                error: DetectStdlibError::Io(_) | DetectStdlibError::LocatingCompiler(_),
+                ..
+            }
+            | Self::CreatingElmHomes(_) => FailureCategory::Infrastructure,
+            Self::ResolvingCompiler {
+                error: DetectStdlibError::Parsing(_),
+                ..
+            }
+            | Self::CompilerDirClash { .. } => FailureCategory::Other,
+        }
+    }
+}
+
 #[allow(clippy::too_many_lines)]
 pub fn compile_and_run_suites<'a, Ps: AsRef<Path> + Send + Sync + 'a>(
     suites: impl IntoParallelIterator<Item = Ps> + 'a,
+    suites_dir: &'a Path,
     instructions: &'a super::cli::Instructions,
+    progress: &'a Progress,
 ) -> Result<impl IntoParallelIterator<Item = CompileAndRunResults<Ps>> + 'a, SuitesError> {
     let (tmp_dir_raw, out_dir) = if let Some(out_dir) = &instructions.config.out_dir {
         (None, out_dir.to_path_buf())
@@ -768,75 +1548,190 @@ pub fn compile_and_run_suites<'a, Ps: AsRef<Path> + Send + Sync + 'a>(
     if !out_dir.exists() {
         let _ = fs::create_dir(&out_dir);
     }
-    let compiler_lock = Mutex::new(());
+    let workers = CompileWorkers::new(instructions.config.compile_jobs())
+        .map_err(SuitesError::CreatingElmHomes)?;
+    let compiler_hashes = Arc::new(CompilerHashes::default());
+    let cache_dir = instructions.config.cache_dir();
+    let cache = cache_dir
+        .as_ref()
+        .filter(|_| !instructions.config.no_cache())
+        .map(|dir| CompileCache::new(dir.join("compile"), Arc::clone(&compiler_hashes)));
+    let passes = cache_dir
+        .as_ref()
+        .filter(|_| instructions.config.incremental())
+        .map(|dir| {
+            Passes::new(
+                dir.join("passes"),
+                Arc::clone(&compiler_hashes),
+                instructions.config.node(),
+            )
+        });
     let prev_runs_failed = AtomicBool::new(false);
 
     let elm_compilers = instructions
         .config
         .elm_compilers()
         .iter()
-        .map(|s| ElmCompilerPath::new_resolved(s.clone()))
-        .collect::<Result<Vec<_>, _>>()
-        .map_err(SuitesError::ResolvingCompiler)?;
+        .map(|compiler| {
+            ElmCompilerPath::new_resolved(compiler).map_err(|error| {
+                SuitesError::ResolvingCompiler {
+                    compiler: compiler.name().to_string(),
+                    error,
+                }
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    for (i, compiler) in elm_compilers.iter().enumerate() {
+        let dir = compiler_dir_name(compiler);
+        if let Some(other) = elm_compilers[..i]
+            .iter()
+            .find(|other| compiler_dir_name(other) == dir)
+        {
+            return Err(SuitesError::CompilerDirClash {
+                first: other.name.clone(),
+                second: compiler.name.clone(),
+                dir,
+            });
+        }
+    }
 
     let scanner = move |suite: Ps| {
         if instructions.fail_fast && prev_runs_failed.load(Ordering::Relaxed) {
             None
         } else {
-            let sscce_out_dir = out_dir.join(suite.as_ref().file_name().expect("todo"));
+            let sscce_out_dir = out_dir.join(sscce_out_dir_name(suite.as_ref(), suites_dir));
+            // Problems reading output.json are reported by `compile_and_run`.
+            let tags = suite_tags(&suite).unwrap_or_default();
 
             if !sscce_out_dir.exists() {
-                let _ = fs::create_dir(&sscce_out_dir);
+                let _ = fs::create_dir_all(&sscce_out_dir);
             }
             if !sscce_out_dir.is_dir() {
                 // TODO(harry): handle this error better
+                progress.suite_finished(true);
                 return Some(CompileAndRunResults {
                     suite,
-                    sscce_out_dir,
+                    tags,
+                    sscce_out_dir: sscce_out_dir.clone(),
                     errors: HashMap::new().also(|hm| {
                         hm.insert(
                             (
                                 elm_compilers[0].clone(),
                                 instructions.config.opt_levels()[0],
                             ),
-                            (0, Some(CompileAndRunError::OutDirIsNotDir)),
+                            SscceRunResult {
+                                out_dir: sscce_out_dir,
+                                retries: 0,
+                                timings: Timings::default(),
+                                run_skipped: false,
+                                cache_lookup: None,
+                                cached_pass: false,
+                                error: Some(CompileAndRunError::OutDirIsNotDir),
+                            },
                         );
                     }),
                 });
             }
 
-            let errors = compile_and_run(
+            let config = instructions.config_layers.config_for(suite.as_ref());
+            let mut fingerprints = HashMap::new();
+            let mut cached_passes = Vec::new();
+            let mut run_types = Vec::new();
+            for (compiler, opt_level) in elm_compilers
+                .iter()
+                .flat_map(|c| config.opt_levels().iter().map(move |o| (c, *o)))
+            {
+                let run_type = (compiler.clone(), opt_level);
+                match passes
+                    .as_ref()
+                    .map(|passes| passes.fingerprint(suite.as_ref(), compiler, opt_level, &config))
+                {
+                    Some(Ok(fingerprint)) => {
+                        if passes.as_ref().unwrap().passed(&fingerprint) {
+                            cached_passes.push(run_type);
+                            continue;
+                        }
+                        fingerprints.insert(run_type.clone(), fingerprint);
+                    }
+                    Some(Err(e)) => debug!(
+                        "Cannot skip {} as it could not be fingerprinted: {}",
+                        suite.as_ref().display(),
+                        e
+                    ),
+                    None => {}
+                }
+                run_types.push(run_type);
+            }
+
+            let mut errors = compile_and_run(
                 &suite,
                 &sscce_out_dir,
-                &compiler_lock,
-                iter_pairs(
-                    elm_compilers.clone(),
-                    instructions.config.opt_levels().par_iter().copied(),
-                ),
-                &instructions.config,
+                &workers,
+                cache.as_ref(),
+                run_types,
+                &config,
+                progress,
             )
             .into_iter()
-            .map(|(opt_level, (retries, res))| {
-                if let Err(CompileAndRunError::RunFailure { .. }) = res {
+            .map(|(run_type, result)| {
+                if let (Some(passes), Some(fingerprint), None) =
+                    (&passes, fingerprints.get(&run_type), &result.error)
+                {
+                    if let Err(e) = passes.record(fingerprint) {
+                        debug!("Could not record pass: {e}");
+                    }
+                }
+                if let Some(
+                    CompileAndRunError::RunFailure { .. }
+                    | CompileAndRunError::Infrastructure(InfrastructureError::Run(_)),
+                ) = result.error
+                {
                     if let Some(dir) = tmp_dir.lock().unwrap().take() {
                         dir.into_path();
                     }
                 } else {
-                    let _ = fs::remove_dir_all(&sscce_out_dir);
+                    let _ = fs::remove_dir_all(&result.out_dir);
+                    // Only removes directories once they are empty.
+                    if let Some(compiler_dir) = result.out_dir.parent() {
+                        remove_empty_dirs(compiler_dir, &out_dir);
+                    }
                 };
-                let failed = match res {
-                    Err(CompileAndRunError::CompileFailure { allowed: true, .. })
-                    | Err(CompileAndRunError::RunFailure { allowed: true, .. })
-                    | Ok(_) => false,
-                    Err(_) => true,
+                let failed = match result.error {
+                    Some(
+                        CompileAndRunError::CompileFailure { allowed: true, .. }
+                        | CompileAndRunError::RunFailure { allowed: true, .. },
+                    )
+                    | None => false,
+                    Some(_) => true,
                 };
                 // Never clear `prev_run_failed`, only set it.
                 prev_runs_failed.fetch_or(failed, Ordering::Relaxed);
-                (opt_level, (retries, res.err()))
+                (run_type, result)
             })
             .collect::<HashMap<_, _>>();
+            for run_type in cached_passes {
+                let out_dir = sscce_out_dir.join(run_out_dir_name(&run_type.0, run_type.1));
+                errors.insert(
+                    run_type,
+                    SscceRunResult {
+                        out_dir,
+                        retries: 0,
+                        timings: Timings::default(),
+                        run_skipped: false,
+                        cache_lookup: None,
+                        cached_pass: true,
+                        error: None,
+                    },
+                );
+            }
+            progress.suite_finished(
+                errors
+                    .values()
+                    .any(|result| Outcome::of(result.error.as_ref()).is_failure()),
+            );
             Some(CompileAndRunResults {
                 suite,
+                tags,
                 sscce_out_dir,
                 errors,
             })
@@ -897,3 +1792,36 @@ impl<'pool> Server<'pool> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{compiler_dir_name, ElmCompilerPath, StdlibVariant};
+    use std::collections::BTreeMap;
+
+    fn compiler(name: &str) -> ElmCompilerPath {
+        ElmCompilerPath {
+            name: name.to_string(),
+            binary: "elm".to_string(),
+            path: "/usr/bin/elm".into(),
+            args: Vec::new(),
+            env: BTreeMap::new(),
+            elm_home: None,
+            stdlib_variant: StdlibVariant::Official,
+        }
+    }
+
+    #[test]
+    fn compiler_dir_names_are_single_components() {
+        for (name, dir) in &[
+            ("elm", "elm"),
+            ("elm-0.19.1", "elm-0.19.1"),
+            ("elm patched", "elm_patched"),
+            ("./build/elm", "._build_elm"),
+            ("C:\\elm", "C__elm"),
+            ("..", "__"),
+            (".", "_"),
+        ] {
+            assert_eq!(compiler_dir_name(&compiler(name)), *dir);
+        }
+    }
+}
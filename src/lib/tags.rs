@@ -0,0 +1,232 @@
+use super::suite;
+use std::fmt;
+use std::iter::Peekable;
+use std::path::PathBuf;
+use std::str::{CharIndices, FromStr};
+
+/// A boolean combination of suite tags, for example `json & !http` or
+/// `(records | tail-calls) & !kernel-bugs`. `and`, `or` and `not` may be used
+/// in place of `&`, `|` and `!`; `,` is the same as `|`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TagExpr {
+    Tag(String),
+    Not(Box<TagExpr>),
+    And(Box<TagExpr>, Box<TagExpr>),
+    Or(Box<TagExpr>, Box<TagExpr>),
+}
+
+impl TagExpr {
+    pub fn matches(&self, tags: &[String]) -> bool {
+        match self {
+            Self::Tag(tag) => tags.contains(tag),
+            Self::Not(expr) => !expr.matches(tags),
+            Self::And(left, right) => left.matches(tags) && right.matches(tags),
+            Self::Or(left, right) => left.matches(tags) || right.matches(tags),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Tag(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tag(tag) => write!(f, "{tag}"),
+            Self::And => write!(f, "&"),
+            Self::Or => write!(f, "|"),
+            Self::Not => write!(f, "!"),
+            Self::Open => write!(f, "("),
+            Self::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_'
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars: Peekable<CharIndices<'_>> = s.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '&' => Token::And,
+            '|' | ',' => Token::Or,
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            c if is_tag_char(c) => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if !is_tag_char(c) {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                }
+                match &s[start..end] {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    tag => Token::Tag(tag.to_string()),
+                }
+            }
+            c => {
+                return Err(format!(
+                    "Unexpected character {c:?} in tag expression {s:?}"
+                ))
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Peekable<std::vec::IntoIter<Token>>,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<TagExpr, String> {
+        let mut expr = self.and()?;
+        while self.tokens.next_if_eq(&Token::Or).is_some() {
+            expr = TagExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<TagExpr, String> {
+        let mut expr = self.not()?;
+        while self.tokens.next_if_eq(&Token::And).is_some() {
+            expr = TagExpr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<TagExpr, String> {
+        match self.tokens.next() {
+            Some(Token::Not) => Ok(TagExpr::Not(Box::new(self.not()?))),
+            Some(Token::Tag(tag)) => Ok(TagExpr::Tag(tag)),
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.tokens.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("Missing `)` in tag expression".to_string()),
+                }
+            }
+            Some(token) => Err(format!("Unexpected `{token}` in tag expression")),
+            None => Err("Tag expression ended unexpectedly".to_string()),
+        }
+    }
+}
+
+impl FromStr for TagExpr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?.into_iter().peekable(),
+        };
+        let expr = parser.or()?;
+        match parser.tokens.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!("Unexpected `{token}` in tag expression {s:?}")),
+        }
+    }
+}
+
+/// Keep the suites whose tags match `include` (if given) and do not match
+/// `skip`. Suites whose output.json cannot be read are kept so that the
+/// problem is reported when they are run.
+pub fn select_suites(
+    suites: &[PathBuf],
+    include: Option<&TagExpr>,
+    skip: Option<&TagExpr>,
+) -> Box<[PathBuf]> {
+    if include.is_none() && skip.is_none() {
+        return suites.into();
+    }
+    suites
+        .iter()
+        .filter(|suite| match suite::suite_tags(suite) {
+            Ok(tags) => {
+                include.is_none_or(|expr| expr.matches(&tags))
+                    && !skip.is_some_and(|expr| expr.matches(&tags))
+            }
+            Err(_) => true,
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TagExpr;
+
+    fn tag(name: &str) -> TagExpr {
+        TagExpr::Tag(name.to_string())
+    }
+
+    fn tags(names: &[&str]) -> Vec<String> {
+        names.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            "a | b & !c".parse(),
+            Ok(TagExpr::Or(
+                Box::new(tag("a")),
+                Box::new(TagExpr::And(
+                    Box::new(tag("b")),
+                    Box::new(TagExpr::Not(Box::new(tag("c"))))
+                ))
+            ))
+        );
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        assert_eq!(
+            "(a | b) & c".parse(),
+            Ok(TagExpr::And(
+                Box::new(TagExpr::Or(Box::new(tag("a")), Box::new(tag("b")))),
+                Box::new(tag("c"))
+            ))
+        );
+    }
+
+    #[test]
+    fn words_and_commas_are_operators() {
+        assert_eq!("a or b and not c".parse::<TagExpr>(), "a | b & !c".parse());
+        assert_eq!("a, b".parse::<TagExpr>(), "a | b".parse());
+    }
+
+    #[test]
+    fn matches_tags() {
+        let expr: TagExpr = "json & !http".parse().unwrap();
+        assert!(expr.matches(&tags(&["json"])));
+        assert!(!expr.matches(&tags(&["json", "http"])));
+        assert!(!expr.matches(&tags(&[])));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for invalid in &["", "   ", "a &", "& a", "(a | b", "a b", "a)", "a $ b", "!"] {
+            assert!(
+                invalid.parse::<TagExpr>().is_err(),
+                "{:?} should not parse",
+                invalid
+            );
+        }
+    }
+}
@@ -5,8 +5,15 @@
 mod lib;
 
 use colored::Colorize;
+use crossbeam::channel::RecvTimeoutError;
+use lib::annotations;
+use lib::baseline::{self, Baseline};
 use lib::cli;
+use lib::config::{Config, FailureCategory};
 use lib::formatting;
+use lib::json_report;
+use lib::junit;
+use lib::progress::Progress;
 use lib::suite;
 use rayon::prelude::*;
 use std::io::Write;
@@ -17,64 +24,61 @@ const WELCOME_MESSAGE: &str = "Elm Torture - stress tests for an elm compiler";
 
 const CATCH_ALL_ERROR_CODE: i32 = 0x28;
 
-#[allow(clippy::enum_glob_use)]
-fn get_exit_code(err: &suite::CompileAndRunError) -> i32 {
-    use suite::CompileAndRunError::*;
-
-    match err {
-        SuiteNotExist
-        | SuiteNotDir
-        | SuiteNotElm
-        | OutDirIsNotDir
-        | CannotGetSuiteConfig(_)
-        | Server(_) => CATCH_ALL_ERROR_CODE,
-
-        CompileFailure { allowed, .. } => {
-            if *allowed {
-                0
-            } else {
-                0x21
-            }
-        }
+fn get_exit_code(err: &suite::CompileAndRunError, config: &Config) -> i32 {
+    err.failure_category()
+        .map_or(0, |category| category_exit_code(category, config))
+}
 
-        RunFailure { allowed, .. } => {
-            if *allowed {
-                0
-            } else {
-                0x22
-            }
-        }
-        ExpectedCompileFailure | ExpectedRunFailure => 0x24,
+fn category_exit_code(category: FailureCategory, config: &Config) -> i32 {
+    if !config.is_fatal(category) {
+        return 0;
+    }
+    match category {
+        FailureCategory::CompileFailure => 0x21,
+        FailureCategory::RunFailure => 0x22,
+        FailureCategory::UnexpectedSuccess => 0x24,
+        FailureCategory::Other => CATCH_ALL_ERROR_CODE,
+        FailureCategory::Timeout => 0x30,
+        FailureCategory::Infrastructure => 0x60,
     }
 }
 
 fn sscce_result_printer(
     suite::CompileAndRunResults {
         suite,
-        sscce_out_dir,
+        tags,
         errors,
+        ..
     }: &suite::CompileAndRunResults<impl AsRef<Path>>,
 ) {
-    let errors_to_print = errors.iter().filter_map(|(ol, (retries, me))| match me {
-        Some(suite::CompileAndRunError::RunFailure { allowed, .. })
-        | Some(suite::CompileAndRunError::CompileFailure { allowed, .. })
-            if *allowed =>
-        {
-            None
-        }
-        e => e.as_ref().map(|ee| (ol, (retries, ee))),
-    });
-    for ((elm_compiler, opt_level), (retries, e)) in errors_to_print {
+    let errors_to_print = errors.iter().filter_map(
+        |(
+            ol,
+            suite::SscceRunResult {
+                out_dir,
+                retries,
+                error: me,
+                ..
+            },
+        )| match me {
+            Some(suite::CompileAndRunError::RunFailure { allowed, .. })
+            | Some(suite::CompileAndRunError::CompileFailure { allowed, .. })
+                if *allowed =>
+            {
+                None
+            }
+            e => e.as_ref().map(|ee| (ol, (out_dir, retries, ee))),
+        },
+    );
+    for ((elm_compiler, opt_level), (out_dir, retries, e)) in errors_to_print {
         println!(
-            "{} compiling with {} in {} optimisation mode\n{}",
+            "{}{} compiling with {} in {} optimisation mode\n{}",
             suite.as_ref().display().to_string().black().on_white(),
+            formatting::tags(tags),
             elm_compiler.to_string().black().on_white(),
             opt_level.to_string().black().on_white(),
             indented::indented(formatting::compile_and_run_error(
-                e,
-                suite,
-                &sscce_out_dir,
-                *retries
+                e, suite, out_dir, *retries
             ))
         );
     }
@@ -83,18 +87,25 @@ fn sscce_result_printer(
 #[allow(clippy::too_many_lines)]
 fn run_suites(
     suites: &[impl AsRef<Path> + Sync],
+    suites_dir: &Path,
+    selection: &str,
     instructions: &cli::Instructions,
 ) -> Option<NonZeroI32> {
     assert!(!suites.is_empty());
     println!(
         "{}
 
-Running the following {} SSCCE{}:
+Running the following {} SSCCE{}{}:
 {}
 ",
         WELCOME_MESSAGE,
         suites.len(),
         if suites.len() == 1 { "" } else { "s" },
+        if selection.is_empty() {
+            String::new()
+        } else {
+            format!(" ({selection})")
+        },
         indented::indented(formatting::easy_format(|f| {
             for path in suites.iter() {
                 writeln!(f, "{}", path.as_ref().display())?
@@ -103,12 +114,32 @@ Running the following {} SSCCE{}:
         }))
     );
 
-    match suite::compile_and_run_suites(suites.par_iter(), instructions) {
+    let progress = Progress::new(suites.len(), instructions.progress);
+    let results =
+        suite::compile_and_run_suites(suites.par_iter(), suites_dir, instructions, &progress);
+    match results {
         Ok(res_iter) => {
-            let suite_results: Vec<_> = res_iter
-                .into_par_iter()
-                .inspect(sscce_result_printer)
-                .collect();
+            let suite_results: Vec<_> = crossbeam::scope(|s| {
+                // The ticker stops when `stop_ticker` is dropped (including
+                // when a worker panics).
+                let (stop_ticker, ticker_stopped) = crossbeam::channel::bounded::<()>(0);
+                let progress = &progress;
+                s.spawn(move |_| {
+                    while let Err(RecvTimeoutError::Timeout) =
+                        ticker_stopped.recv_timeout(progress.interval())
+                    {
+                        progress.tick();
+                    }
+                });
+                let suite_results = res_iter
+                    .into_par_iter()
+                    .inspect(|results| progress.suspend(|| sscce_result_printer(results)))
+                    .collect();
+                drop(stop_ticker);
+                suite_results
+            })
+            .unwrap();
+            progress.suspend(|| ());
 
             println!(
                 "
@@ -118,12 +149,28 @@ elm-torture has run the following {} SSCCE{}:
                 suites.len(),
                 if suites.len() == 1 { "" } else { "s" },
                 indented::indented(formatting::easy_format(|f| {
+                    if instructions.summary == cli::SummaryStyle::Matrix {
+                        return write!(f, "{}", formatting::result_matrix(&suite_results));
+                    }
                     let mut opt_levels_of_interest = HashSet::new();
                     loop {
                         let mut current_opt_level = None;
-                        for suite::CompileAndRunResults { suite, errors, .. } in &suite_results {
-                            use suite::CompileAndRunError;
-                            for (sscce_run_type, (_, possible_error)) in errors.iter() {
+                        for suite::CompileAndRunResults {
+                            suite,
+                            tags,
+                            errors,
+                            ..
+                        } in &suite_results
+                        {
+                            for (
+                                sscce_run_type,
+                                suite::SscceRunResult {
+                                    error: possible_error,
+                                    cached_pass,
+                                    ..
+                                },
+                            ) in errors.iter()
+                            {
                                 let (compiler, run_opt_level) = sscce_run_type;
                                 let should_print = if let Some(ol) = current_opt_level {
                                     ol == sscce_run_type
@@ -142,26 +189,22 @@ elm-torture has run the following {} SSCCE{}:
                                 };
                                 if should_print {
                                     writeln_indented!(
-                                f,
-                                "{} ({})",
-                                suite.as_ref().display(),
-                                match possible_error {
-                                    Some(CompileAndRunError::RunFailure {
-                                        allowed: true, ..
-                                    }) => "allowed run failure".yellow(),
-                                    Some(CompileAndRunError::CompileFailure {
-                                        allowed: true,
-                                        ..
-                                    }) => "allowed compile failure".yellow(),
-                                    Some(CompileAndRunError::ExpectedCompileFailure) =>
-                                        "success when elm-torture expected a compile time failure"
-                                            .red(),
-                                    Some(CompileAndRunError::ExpectedRunFailure) =>
-                                        "success when elm-torture expected a run time failure".red(),
-                                    Some(_) => "failure".red(),
-                                    None => "success".green(),
-                                }
-                            )?
+                                        f,
+                                        "{}{} ({})",
+                                        suite.as_ref().display(),
+                                        formatting::tags(tags),
+                                        match possible_error {
+                                            None if *cached_pass => "cached pass".green(),
+                                            None => "success".green(),
+                                            Some(e) => match e.failure_category() {
+                                                None =>
+                                                    suite::Outcome::of(Some(e)).to_string().yellow(),
+                                                Some(FailureCategory::Timeout) => "timeout".red(),
+                                                Some(_) =>
+                                                    suite::Outcome::of(Some(e)).to_string().red(),
+                                            },
+                                        }
+                                    )?
                                 }
                             }
                         }
@@ -172,18 +215,92 @@ elm-torture has run the following {} SSCCE{}:
                     Ok(())
                 }))
             );
-            let code = suite_results
+            if let Some(cache_dir) = instructions
+                .config
+                .cache_dir()
+                .filter(|_| !instructions.config.no_cache())
+            {
+                println!("{}\n", formatting::cache_stats(&suite_results, &cache_dir));
+            }
+            if instructions.github_annotations {
+                print!("{}", annotations::github(&suite_results));
+            }
+            if instructions.slowest > 0 {
+                println!(
+                    "{}",
+                    formatting::slowest_suites(&suite_results, instructions.slowest)
+                );
+            }
+            let baseline = Baseline::from_results(&suite_results, suites_dir);
+            let regressions = instructions.compare_baseline.as_ref().map(|old| {
+                let comparison = baseline.compare(old);
+                println!("{}", formatting::baseline_comparison(&comparison));
+                comparison
+                    .newly_failing
+                    .into_iter()
+                    .map(|change| change.key)
+                    .collect::<HashSet<_>>()
+            });
+            let regressions = regressions.as_ref();
+            let mut code = suite_results
                 .iter()
-                .flat_map(|suite::CompileAndRunResults { errors, .. }| {
-                    errors.iter().filter_map(|(_, (_, e))| e.as_ref())
+                .flat_map(|suite::CompileAndRunResults { suite, errors, .. }| {
+                    errors
+                        .iter()
+                        .filter(move |((compiler, opt_level), _)| {
+                            regressions.is_none_or(|regressions| {
+                                regressions.contains(&baseline::Key::new(
+                                    suite.as_ref(),
+                                    suites_dir,
+                                    compiler.name(),
+                                    *opt_level,
+                                ))
+                            })
+                        })
+                        .filter_map(|(_, r)| r.error.as_ref())
                 })
-                .fold(0, |code, error| code | get_exit_code(error));
+                .fold(0, |code, error| {
+                    code | get_exit_code(error, &instructions.config)
+                });
+            if let Some(baseline_file) = &instructions.save_baseline {
+                if let Err(e) = baseline.write(baseline_file) {
+                    eprintln!(
+                        "Could not write baseline to {}. Details:\n{}",
+                        baseline_file.display(),
+                        e
+                    );
+                    code |= CATCH_ALL_ERROR_CODE;
+                }
+            }
+            if let Some(junit_file) = &instructions.junit {
+                if let Err(e) = junit::write_report(junit_file, &suite_results) {
+                    eprintln!(
+                        "Could not write JUnit report to {}. Details:\n{}",
+                        junit_file.display(),
+                        e
+                    );
+                    code |= CATCH_ALL_ERROR_CODE;
+                }
+            }
+            if let Some(report_file) = &instructions.report_json {
+                if let Err(e) = json_report::write_report(report_file, &suite_results) {
+                    eprintln!(
+                        "Could not write JSON report to {}. Details:\n{}",
+                        report_file.display(),
+                        e
+                    );
+                    code |= CATCH_ALL_ERROR_CODE;
+                }
+            }
             NonZeroI32::new(code)
         }
         Err(e) => {
             println!("{}", formatting::suites_error(&e));
 
-            NonZeroI32::new(CATCH_ALL_ERROR_CODE)
+            NonZeroI32::new(category_exit_code(
+                e.failure_category(),
+                &instructions.config,
+            ))
         }
     }
 }
@@ -197,8 +314,55 @@ fn run_app(instructions: cli::Instructions) -> Option<NonZeroI32> {
             writeln!(&mut file).expect("could not write to file config");
             None
         }
+        cli::Task::ExplainConfig => {
+            print!(
+                "{}",
+                formatting::explain_config(&instructions.config_layers)
+            );
+            None
+        }
         cli::Task::RunSuites(ref suite_dir) => match lib::find_suites::find_suites(&suite_dir) {
-            Ok(suites) => run_suites(&suites, &instructions),
+            Ok(found) => {
+                let suites = lib::find_suites::filter_suites(
+                    &found,
+                    suite_dir,
+                    &instructions.filter,
+                    &instructions.exclude,
+                );
+                let suites = lib::tags::select_suites(
+                    &suites,
+                    instructions.tags.as_ref(),
+                    instructions.skip_tags.as_ref(),
+                );
+                if suites.is_empty() {
+                    eprintln!(
+                        "None of the {} suites in {} were selected by --filter, --exclude, --tags and --skip-tags.",
+                        found.len(),
+                        suite_dir.display()
+                    );
+                    return NonZeroI32::new(CATCH_ALL_ERROR_CODE);
+                }
+                let mut selection = Vec::new();
+                if suites.len() < found.len() {
+                    selection.push(format!(
+                        "{} found, {} filtered out",
+                        found.len(),
+                        found.len() - suites.len()
+                    ));
+                }
+                let suites = if let Some(shard) = &instructions.shard {
+                    selection.push(format!("shard {} of {} suites", shard, suites.len()));
+                    let suites = shard.select(&suites, instructions.shard_timings.as_ref());
+                    if suites.is_empty() {
+                        println!("{WELCOME_MESSAGE}\n\nShard {shard} has no suites to run.");
+                        return None;
+                    }
+                    suites
+                } else {
+                    suites
+                };
+                run_suites(&suites, suite_dir, &selection.join(", "), &instructions)
+            }
             Err(ref err) => {
                 eprint!("{}", formatting::find_suite_error(err, suite_dir));
                 NonZeroI32::new(0x28)